@@ -0,0 +1,133 @@
+"""Headless engine-vs-engine matches, e.g. alpha-beta against MCTS.
+
+    python arena.py --engine1 alphabeta --engine2 mcts --games 10 --depth 5 --time 5
+    python arena.py --clock 60 --inc 0.5 --depth 30   # game clock instead of time per move
+    python arena.py --engine2 alphabeta --skill2 8     # weakened engine, e.g. to calibrate skill levels
+    python arena.py --record games.jsonl               # save the games, e.g. for book.py
+    python arena.py --engine2 alphabeta --eval2 weights.txt   # test a set of evaluation weights
+
+Colours alternate every game. Results are reported from engine 1's point of view.
+With --clock an engine that runs out of time loses.
+"""
+import argparse
+import json
+import math
+import time as clock_time
+import numpy as np
+import fianco_brain
+from fianco_brain import FiancoAI
+
+ROWS = 9
+
+# The engine's starting position, the one recorded games and the tuner start from
+START_BOARD = np.array(fianco_brain.START_BOARD, dtype=np.int8)
+
+
+def play_game(algorithms, depth, time, max_plies, clock=None, increment=0.0, contempts=None, skills=None, moves=None,
+              eval_files=None):
+    """Plays one game. algorithms maps player (-1 White, 1 Black) to an algorithm name.
+    With a clock (seconds per side) the engines manage their own time.
+    contempts optionally maps player to the engine's draw contempt, skills to its skill level (0-20),
+    eval_files to a file of evaluation weights for it (see FiancoAI.load_eval_params).
+    moves, if given, is a list that receives the moves played.
+    Returns the winning player, or 0 for a draw."""
+    board = START_BOARD.copy()
+    ais = {player: FiancoAI(player, 1, algorithm) for player, algorithm in algorithms.items()}
+    for player, contempt in (contempts or {}).items():
+        ais[player].set_contempt(contempt)
+    for player, skill in (skills or {}).items():
+        ais[player].set_skill_level(skill)
+    for player, eval_file in (eval_files or {}).items():
+        if eval_file is not None:
+            ais[player].load_eval_params(eval_file)
+    remaining_ms = {-1: clock * 1000, 1: clock * 1000} if clock else None
+    seen = {}
+    moves = [] if moves is None else moves  # Played so far; the engines replay them for repetition detection
+    player = -1
+    for _ in range(max_plies):
+        key = (board.tobytes(), player)
+        seen[key] = seen.get(key, 0) + 1
+        if seen[key] >= 3:
+            return 0  # Threefold repetition
+        start = clock_time.monotonic()
+        ais[player].set_history_moves(START_BOARD, -1, moves)
+        try:
+            if remaining_ms is None:
+                _, pv = ais[player].get_best_move(board, player, depth, time)
+            else:
+                _, pv = ais[player].get_best_move(board, player, depth, time, remaining_ms=int(remaining_ms[player]),
+                                                  increment_ms=int(increment * 1000))
+        except ValueError:
+            return -player  # No moves left: the side to move loses
+        if remaining_ms is not None:
+            remaining_ms[player] -= (clock_time.monotonic() - start) * 1000
+            if remaining_ms[player] < 0:
+                print(f"{'White' if player == -1 else 'Black'} lost on time")
+                return -player
+            remaining_ms[player] += increment * 1000
+        from_row, from_col, to_row, to_col = pv[0]
+        moves.append(pv[0])
+        board[from_row, from_col] = 0
+        board[to_row, to_col] = player
+        if abs(from_row - to_row) == 2:
+            board[(from_row + to_row) // 2, (from_col + to_col) // 2] = 0
+        if to_row == (ROWS - 1 if player == 1 else 0):
+            return player
+        player = -player
+    return 0
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Play automated matches between two Fianco engines.")
+    parser.add_argument("--engine1", default="alphabeta", help="'alphabeta' or 'mcts'")
+    parser.add_argument("--engine2", default="mcts", help="'alphabeta' or 'mcts'")
+    parser.add_argument("--games", type=int, default=10)
+    parser.add_argument("--depth", type=int, default=5)
+    parser.add_argument("--time", type=float, default=5, help="seconds per move")
+    parser.add_argument("--clock", type=float, default=None, help="seconds per side for the whole game")
+    parser.add_argument("--inc", type=float, default=0.0, help="increment in seconds per move (with --clock)")
+    parser.add_argument("--max-plies", type=int, default=300)
+    parser.add_argument("--contempt1", type=int, default=30, help="draw contempt of engine 1 (negative accepts draws)")
+    parser.add_argument("--contempt2", type=int, default=30, help="draw contempt of engine 2")
+    parser.add_argument("--skill1", type=int, default=20, help="skill level of engine 1 (0-20, 20 is full strength)")
+    parser.add_argument("--skill2", type=int, default=20, help="skill level of engine 2")
+    parser.add_argument("--eval1", default=None, help="file of evaluation weights for engine 1 (default weights if omitted)")
+    parser.add_argument("--eval2", default=None, help="file of evaluation weights for engine 2")
+    parser.add_argument("--record", default=None, help="append the games to this file, one JSON object per line")
+    args = parser.parse_args()
+
+    wins = draws = losses = 0
+    for game in range(args.games):
+        # Engine 1 plays White in even games and Black in odd games
+        engine1_side = -1 if game % 2 == 0 else 1
+        algorithms = {engine1_side: args.engine1, -engine1_side: args.engine2}
+        contempts = {engine1_side: args.contempt1, -engine1_side: args.contempt2}
+        skills = {engine1_side: args.skill1, -engine1_side: args.skill2}
+        eval_files = {engine1_side: args.eval1, -engine1_side: args.eval2}
+        moves = []
+        winner = play_game(algorithms, args.depth, args.time, args.max_plies, args.clock, args.inc, contempts, skills, moves,
+                           eval_files)
+        if args.record:
+            with open(args.record, 'a') as f:
+                f.write(json.dumps({"moves": [list(move) for move in moves], "winner": winner}) + "\n")
+        if winner == 0:
+            draws += 1
+            result = "draw"
+        elif winner == engine1_side:
+            wins += 1
+            result = f"{args.engine1} wins"
+        else:
+            losses += 1
+            result = f"{args.engine2} wins"
+        colour = "White" if engine1_side == -1 else "Black"
+        print(f"Game {game + 1}: {args.engine1} as {colour} -> {result}")
+
+    print(f"{args.engine1} vs {args.engine2}: +{wins} ={draws} -{losses}")
+    # Measured Elo difference, to calibrate skill levels against their targets
+    score = (wins + draws / 2) / args.games
+    if 0 < score < 1:
+        print(f"Elo difference: {-400 * math.log10(1 / score - 1):+.0f}")
+
+
+if __name__ == "__main__":
+    main()
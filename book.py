@@ -0,0 +1,41 @@
+"""Builds the opening book the engine plays from (see FiancoAI.load_book).
+
+    python book.py --self-play 200 --nodes 20000           # engine games from the start position
+    python book.py --games games.jsonl --out my_book.bin   # games recorded with arena.py --record
+
+Both sources can be combined. Only the first --plies plies of each game go into the book,
+and moves played fewer than --min-games times are left out.
+"""
+import argparse
+import json
+from fianco_brain import BookBuilder
+from arena import START_BOARD
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Build a Fianco opening book from game records and self-play.")
+    parser.add_argument("--games", action="append", default=[], help="JSON lines file of games ({'moves': [...], 'winner': -1/0/1})")
+    parser.add_argument("--self-play", type=int, default=0, help="number of engine games to play")
+    parser.add_argument("--nodes", type=int, default=20000, help="nodes per move in self-play")
+    parser.add_argument("--skill", type=int, default=16, help="skill level for the book moves in self-play (variety)")
+    parser.add_argument("--plies", type=int, default=16, help="book depth in plies")
+    parser.add_argument("--min-games", type=int, default=1)
+    parser.add_argument("--out", default="fianco_book.bin")
+    args = parser.parse_args()
+
+    builder = BookBuilder(args.plies)
+    for path in args.games:
+        with open(path) as f:
+            for line in f:
+                if line.strip():
+                    game = json.loads(line)
+                    builder.add_game(START_BOARD, -1, [tuple(move) for move in game["moves"]], game["winner"])
+    if args.self_play:
+        print(f"Playing {args.self_play} self-play games...")
+        builder.add_self_play(START_BOARD, args.self_play, args.nodes, args.skill)
+    entries = builder.save(args.out, args.min_games)
+    print(f"{builder.positions()} positions, {entries} book moves written to {args.out}")
+
+
+if __name__ == "__main__":
+    main()
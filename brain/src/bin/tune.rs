@@ -0,0 +1,8 @@
+// Fits the evaluation weights to game records; see `fianco_brain::tuning::run`
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(message) = fianco_brain::tuning::run(&args) {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+}
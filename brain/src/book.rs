@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::fileformat::{invalid_data, read_magic};
+use crate::search::{zobrist_key, zobrist_table, AlphaBeta};
+use crate::searcher::{SearchLimits, Searcher};
+use crate::skill::{Skill, SKILL_LINES};
+use crate::{apply_move, get_valid_moves, is_game_over, is_winner, Board, Move, COLS, ROWS};
+
+const MAGIC: &[u8; 4] = b"FOB2"; // 2: Zobrist keys from splitmix64
+const ENTRY_BYTES: usize = 18; // Key, from and to square, games and score
+const BLACK_TO_MOVE: u64 = 0x9e37_79b9_7f4a_7c15; // Mixed into the key when Black is to move
+const MAX_GAME_PLIES: usize = 300; // Self-play games this long count as a draw
+pub(crate) const DEFAULT_BOOK_DEPTH: usize = 16; // Plies from the start position
+
+/// Statistics of one book move: how often it was played and the half-points
+/// the side that played it scored (2 per win, 1 per draw).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BookMove {
+    pub mv: Move,
+    pub games: u32,
+    pub score: u32,
+}
+
+impl BookMove {
+    // Share of games played, times the score rate with one draw added so
+    // a move played once is neither certain nor ruled out
+    fn weight(&self) -> f64 {
+        self.games as f64 * (self.score as f64 + 1.0) / (2.0 * self.games as f64 + 2.0)
+    }
+}
+
+/// Opening moves by position. On disk the entries are sorted by key, each
+/// `ENTRY_BYTES` long: the Zobrist key of the position with the side to move
+/// mixed in, the from and to squares (`row * 9 + col`) and the two counters
+/// of `BookMove`, all little endian.
+pub(crate) struct OpeningBook {
+    entries: Vec<(u64, BookMove)>,
+    zobrist_table: Vec<Vec<[u64; 2]>>,
+}
+
+impl OpeningBook {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The book moves of the position, most played first.
+    pub fn probe(&self, board: &Board, player: i8) -> Vec<BookMove> {
+        let key = book_key(&self.zobrist_table, board, player);
+        let start = self.entries.partition_point(|&(k, _)| k < key);
+        let mut moves: Vec<BookMove> =
+            self.entries[start..].iter().take_while(|&&(k, _)| k == key).map(|&(_, mv)| mv).collect();
+        moves.sort_by_key(|mv| std::cmp::Reverse(mv.games));
+        moves
+    }
+
+    /// Picks one of the legal book moves at random, weighted by how often it
+    /// was played and how well it scored.
+    pub fn choose(&self, board: &Board, player: i8, rng: &mut impl Rng) -> Option<Move> {
+        let legal = get_valid_moves(board, player);
+        let moves: Vec<BookMove> = self.probe(board, player).into_iter().filter(|mv| legal.contains(&mv.mv)).collect();
+        let total: f64 = moves.iter().map(BookMove::weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut pick = rng.gen::<f64>() * total;
+        for mv in &moves {
+            if pick < mv.weight() {
+                return Some(mv.mv);
+            }
+            pick -= mv.weight();
+        }
+        moves.last().map(|mv| mv.mv)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for (key, mv) in &self.entries {
+            let (from_row, from_col, to_row, to_col) = mv.mv;
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&[(from_row * COLS + from_col) as u8, (to_row * COLS + to_col) as u8])?;
+            writer.write_all(&mv.games.to_le_bytes())?;
+            writer.write_all(&mv.score.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        read_magic(&mut reader, MAGIC, "opening book")?;
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len) as usize;
+
+        let mut entries = Vec::new();
+        let mut entry = [0u8; ENTRY_BYTES];
+        for _ in 0..len {
+            reader.read_exact(&mut entry)?;
+            let key = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+            let (from, to) = (entry[8] as usize, entry[9] as usize);
+            if from >= ROWS * COLS || to >= ROWS * COLS {
+                return Err(invalid_data("corrupt opening book file"));
+            }
+            let mv = BookMove {
+                mv: (from / COLS, from % COLS, to / COLS, to % COLS),
+                games: u32::from_le_bytes(entry[10..14].try_into().unwrap()),
+                score: u32::from_le_bytes(entry[14..18].try_into().unwrap()),
+            };
+            if entries.last().is_some_and(|&(last, _)| last > key) {
+                return Err(invalid_data("corrupt opening book file"));
+            }
+            entries.push((key, mv));
+        }
+        Ok(OpeningBook { entries, zobrist_table: zobrist_table() })
+    }
+}
+
+/// Collects move statistics from games for the first `max_plies` plies and
+/// turns them into an `OpeningBook`.
+pub(crate) struct BookBuilder {
+    pub max_plies: usize,
+    stats: HashMap<(u64, Move), (u32, u32)>,
+    zobrist_table: Vec<Vec<[u64; 2]>>,
+}
+
+impl BookBuilder {
+    pub fn new(max_plies: usize) -> Self {
+        BookBuilder { max_plies, stats: HashMap::new(), zobrist_table: zobrist_table() }
+    }
+
+    /// Positions in the builder so far.
+    pub fn positions(&self) -> usize {
+        let mut keys: Vec<u64> = self.stats.keys().map(|&(key, _)| key).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys.len()
+    }
+
+    /// Adds a game played from `board` with `player` to move first and won
+    /// by `winner` (-1 White, 1 Black, 0 draw). Fails on the first illegal
+    /// move, naming its index, without adding anything.
+    pub fn add_game(&mut self, board: &Board, player: i8, moves: &[Move], winner: i8) -> Result<(), usize> {
+        let mut board = *board;
+        let mut player = player;
+        let mut entries = Vec::new();
+        for (i, &mv) in moves.iter().enumerate() {
+            if is_game_over(&board, player) || !get_valid_moves(&board, player).contains(&mv) {
+                return Err(i);
+            }
+            if i < self.max_plies {
+                let score = if winner == player { 2 } else if winner == 0 { 1 } else { 0 };
+                entries.push((book_key(&self.zobrist_table, &board, player), mv, score));
+            }
+            apply_move(&mut board, player, mv);
+            player = -player;
+        }
+        for (key, mv, score) in entries {
+            let stats = self.stats.entry((key, mv)).or_insert((0, 0));
+            stats.0 += 1;
+            stats.1 += score;
+        }
+        Ok(())
+    }
+
+    /// Plays `games` games from `board`, White to move, between two
+    /// alpha-beta engines searching `nodes` nodes per move and adds them.
+    /// Within the book plies the moves are drawn from the best few at
+    /// `skill_level`, so the games branch; after that both sides play their
+    /// best move.
+    pub fn add_self_play(&mut self, board: &Board, games: usize, nodes: u64, skill_level: u32) {
+        let limits = SearchLimits { max_depth: 64, time: None, nodes: Some(nodes), deterministic: false };
+        let mut skill = Skill::new();
+        skill.level = skill_level;
+        // Made once, as each comes with a full-size transposition table;
+        // every game starts them afresh
+        let mut engines = [AlphaBeta::new(-1, 1), AlphaBeta::new(1, 1)];
+        for _ in 0..games {
+            for engine in engines.iter_mut() {
+                engine.clear_hash();
+                engine.new_game();
+            }
+            let mut position = *board;
+            let mut player = -1;
+            let mut played: Vec<Board> = Vec::new();
+            let mut moves = Vec::new();
+            let mut winner = 0;
+            while moves.len() < MAX_GAME_PLIES {
+                if is_game_over(&position, player) {
+                    winner = -player;
+                    break;
+                }
+                if played.iter().filter(|&&earlier| earlier == position).count() >= 2 {
+                    break; // Threefold repetition
+                }
+                let engine = &mut engines[if player == -1 { 0 } else { 1 }];
+                let in_book = moves.len() < self.max_plies;
+                engine.multi_pv = if in_book { SKILL_LINES } else { 1 };
+                engine.set_game_history(&played);
+                let Some(result) = engine.search(&position, player, &limits) else {
+                    winner = -player;
+                    break;
+                };
+                let result = if in_book { skill.choose(result, player, 1) } else { result };
+                let mv = result.pv[0];
+                played.push(position);
+                moves.push(mv);
+                apply_move(&mut position, player, mv);
+                if is_winner(&position, player) {
+                    winner = player;
+                    break;
+                }
+                player = -player;
+            }
+            self.add_game(board, -1, &moves, winner).expect("self-play move is legal");
+        }
+    }
+
+    /// The book of every move played at least `min_games` times.
+    pub fn build(&self, min_games: u32) -> OpeningBook {
+        let mut entries: Vec<(u64, BookMove)> = self
+            .stats
+            .iter()
+            .filter(|(_, &(games, _))| games >= min_games.max(1))
+            .map(|(&(key, mv), &(games, score))| (key, BookMove { mv, games, score }))
+            .collect();
+        entries.sort_by_key(|&(key, mv)| (key, mv.mv));
+        OpeningBook { entries, zobrist_table: self.zobrist_table.clone() }
+    }
+}
+
+fn book_key(zobrist_table: &[Vec<[u64; 2]>], board: &Board, player: i8) -> u64 {
+    let key = zobrist_key(zobrist_table, board);
+    if player == 1 {
+        key ^ BLACK_TO_MOVE
+    } else {
+        key
+    }
+}
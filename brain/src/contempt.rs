@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+use crate::DRAW_SCORE;
+
+const MAX_CONTEMPT: i32 = 300; // Bound of the effective contempt, about 15 stones
+const TREND_SEARCHES: usize = 4; // The trend is the average root score of this many recent searches
+const MAX_TREND_SCORE: i32 = 500; // Root scores are clamped to this first, so found wins don't dominate
+const ELO_PER_UNIT: i32 = 10; // Each this many Elo of advantage over the opponent add one unit
+
+/// How much the engine dislikes draws, in evaluation units: a draw scores
+/// `-value` for the engine and `+value` for its opponent. On top of the
+/// fixed `base`, `trend_percent` adds that share of the engine's recent root
+/// scores (a better position avoids draws, a worse one seeks them) and
+/// `elo_advantage` (positive against weaker opponents) one unit per
+/// `ELO_PER_UNIT` Elo.
+#[derive(Debug, Clone)]
+pub(crate) struct Contempt {
+    pub base: i32,
+    pub trend_percent: i32,
+    pub elo_advantage: i32,
+    recent_scores: VecDeque<i32>,
+}
+
+impl Contempt {
+    pub fn new() -> Self {
+        Contempt {
+            base: -DRAW_SCORE,
+            trend_percent: 0,
+            elo_advantage: 0,
+            recent_scores: VecDeque::with_capacity(TREND_SEARCHES),
+        }
+    }
+
+    /// Records the root score of a search from the engine's point of view.
+    pub fn record(&mut self, score: i32) {
+        if self.recent_scores.len() == TREND_SEARCHES {
+            self.recent_scores.pop_front();
+        }
+        self.recent_scores.push_back(score.clamp(-MAX_TREND_SCORE, MAX_TREND_SCORE));
+    }
+
+    /// Forgets the recorded scores, e.g. for a new game.
+    pub fn clear_trend(&mut self) {
+        self.recent_scores.clear();
+    }
+
+    /// The contempt for the next search. Without `use_trend` the recorded
+    /// scores are ignored, so the value only depends on the settings.
+    pub fn value(&self, use_trend: bool) -> i32 {
+        let mut value = self.base + self.elo_advantage / ELO_PER_UNIT;
+        if use_trend && !self.recent_scores.is_empty() {
+            let trend = self.recent_scores.iter().sum::<i32>() / self.recent_scores.len() as i32;
+            value += trend * self.trend_percent / 100;
+        }
+        value.clamp(-MAX_CONTEMPT, MAX_CONTEMPT)
+    }
+}
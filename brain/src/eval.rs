@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::fileformat::invalid_data;
+use crate::WIN_BY_RACE;
+
+/// Names of the weights, in the order files list them.
+pub(crate) const PARAM_NAMES: [&str; 4] = ["stone", "advance", "edge", "race_win"];
+
+/// Weights of the evaluation, in evaluation units: `stone` per stone,
+/// `advance` per row a stone has advanced, `edge` per column a stone is away
+/// from the centre column, and `race_win` for a race the race module proves
+/// won, less the plies it takes. The alpha-beta search scores proven races as
+/// wins instead, so `race_win` only matters to MCTS.
+///
+/// On disk the weights are plain text, one `name = value` per line; blank
+/// lines and everything after a `#` are ignored, and weights left out keep
+/// their default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EvalParams {
+    pub stone: i32,
+    pub advance: i32,
+    pub edge: i32,
+    pub race_win: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams { stone: 20, advance: 3, edge: 2, race_win: WIN_BY_RACE }
+    }
+}
+
+impl EvalParams {
+    pub fn get(&self, name: &str) -> Option<i32> {
+        match name {
+            "stone" => Some(self.stone),
+            "advance" => Some(self.advance),
+            "edge" => Some(self.edge),
+            "race_win" => Some(self.race_win),
+            _ => None,
+        }
+    }
+
+    /// Sets the weight called `name`; fails on an unknown name.
+    pub fn set(&mut self, name: &str, value: i32) -> Result<(), String> {
+        let weight = match name {
+            "stone" => &mut self.stone,
+            "advance" => &mut self.advance,
+            "edge" => &mut self.edge,
+            "race_win" => &mut self.race_win,
+            _ => return Err(format!("unknown evaluation weight '{}' (known: {})", name, PARAM_NAMES.join(", "))),
+        };
+        *weight = value;
+        Ok(())
+    }
+
+    /// Every weight by name, in `PARAM_NAMES` order.
+    pub fn values(&self) -> Vec<(&'static str, i32)> {
+        PARAM_NAMES.iter().map(|&name| (name, self.get(name).unwrap())).collect()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text: String = self.values().iter().map(|(name, value)| format!("{} = {}\n", name, value)).collect();
+        fs::write(path, text)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut params = EvalParams::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: String| invalid_data(&format!("line {}: {}", number + 1, message));
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(invalid("expected 'name = value'".to_string()));
+            };
+            let value = value.trim().parse().map_err(|_| invalid(format!("'{}' is not an integer", value.trim())))?;
+            params.set(name.trim(), value).map_err(invalid)?;
+        }
+        Ok(params)
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::{self, Read};
+
+// Every data file the engine writes starts with a four-byte magic: three
+// letters naming the kind of file and a version digit. The digit changes
+// whenever the stored data would mean something else to a newer engine
+// (other Zobrist keys, other scores), so such files are refused rather
+// than misread.
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the magic at the start of a `kind` file, failing unless it is
+/// `magic` itself.
+pub(crate) fn read_magic(reader: &mut impl Read, magic: &[u8; 4], kind: &str) -> io::Result<()> {
+    let mut found = [0u8; 4];
+    reader.read_exact(&mut found)?;
+    if found[..3] != magic[..3] {
+        return Err(invalid_data(&format!("not a Fianco {} file", kind)));
+    }
+    if found[3] != magic[3] {
+        return Err(invalid_data(&format!("{} file of another engine version", kind)));
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::Move;
+
+pub(crate) const INFO_INTERVAL: Duration = Duration::from_millis(500); // Between reports while an iteration runs
+const MAX_QUEUED_INFO: usize = 256; // The oldest reports are dropped when nobody polls
+
+/// Progress report of a running search, sent after each completed
+/// iteration (one per line in multi-PV mode, `multipv` counting from 1) and
+/// every `INFO_INTERVAL` in between. Reports in between carry the depth being
+/// searched with the score and PV of the last completed iteration. The score
+/// is from White's point of view; `nodes` and `time` count from the start.
+#[derive(Debug, Clone)]
+pub(crate) struct SearchInfo {
+    pub depth: i32,
+    pub multipv: usize,
+    pub score: i32,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/// Reports waiting to be polled. Searchers send from any thread; the
+/// Python side drains the queue while the search runs with the GIL released.
+#[derive(Default)]
+pub(crate) struct InfoQueue {
+    reports: Mutex<VecDeque<SearchInfo>>,
+}
+
+impl InfoQueue {
+    pub fn send(&self, info: SearchInfo) {
+        if let Ok(mut reports) = self.reports.lock() {
+            if reports.len() == MAX_QUEUED_INFO {
+                reports.pop_front();
+            }
+            reports.push_back(info);
+        }
+    }
+
+    /// Takes every waiting report, oldest first.
+    pub fn drain(&self) -> Vec<SearchInfo> {
+        match self.reports.lock() {
+            Ok(mut reports) => reports.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut reports) = self.reports.lock() {
+            reports.clear();
+        }
+    }
+}
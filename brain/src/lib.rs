@@ -1,98 +1,148 @@
 use numpy::PyArray2;
 use pyo3::prelude::*;
 
+mod book;
+mod contempt;
+mod eval;
+mod fileformat;
+mod info;
+mod mcts;
+mod pns;
+mod race;
+mod search;
+mod searcher;
+mod skill;
+mod tablebase;
+mod timeman;
+mod tt;
+#[cfg(feature = "tuner")]
+pub mod tuning;
+
 // use core::hash;
-use std::cmp::{max, min};
-use std::collections::HashMap;
 // use std::hash::{Hash, Hasher};
 // use std::collections::hash_map::DefaultHasher;
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
-use std::time::{Instant, Duration};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use ndarray::ArrayView2;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use book::{BookBuilder, OpeningBook, DEFAULT_BOOK_DEPTH};
+use eval::EvalParams;
+use info::{InfoQueue, SearchInfo};
+use mcts::{Mcts, Playout};
+use search::AlphaBeta;
+use searcher::{Algorithm, RootFilter, SearchLimits, SearchResult, SearchStats, Searcher};
+use skill::{Skill, MAX_SKILL, SKILL_LINES};
+use tablebase::{Tablebase, TbValue};
+use timeman::TimeLimits;
 
 const ROWS: usize = 9;
 const COLS: usize = 9;
 const MAX_SCORE: i32 = 1_000_000;
 const MIN_SCORE: i32 = -MAX_SCORE;
+// Won and lost positions score MAX_SCORE minus the distance in plies. Wins
+// proven with only an upper bound on their distance (by the proof-number or
+// race solver) score another MAX_PLY lower, so they rank below every exact
+// distance, and anything from FORCED_SCORE up is a forced result
+const MAX_PLY: i32 = 1_000;
+const FORCED_SCORE: i32 = MAX_SCORE - 2 * MAX_PLY;
+// Value of a draw for the engine unless contempt is configured
 const DRAW_SCORE: i32 = -30;
 // const LOSS_BY_TRIANGLE: i32 = -MAX_SCORE/2;
-const WIN_BY_TRIANGLE: i32 = 50_000;
-const MAX_TT_SIZE: usize = 40_000_000; //INCREASE WHEN PLAYING AGAINST ANOTHER PLAYER
-
-// Define the possible flags for entries
-#[derive(Debug, Clone, Copy)]
-enum TTFlag {
-    Exact,
-    LowerBound,
-    UpperBound,
-}
-
-// Structure for a transposition table entry
-#[derive(Debug, Clone, Copy)]
-struct TTEntry {
-    best_move: Option<(usize, usize, usize, usize)>,
-    eval: i32,
-    depth: i32,
-    flag: TTFlag,
-}
-
-type TranspositionTable = HashMap<u64, TTEntry>;
+// Default evaluation of a race proven by the race module, less the plies it takes
+const WIN_BY_RACE: i32 = 50_000;
 
 type Board = [[i8; COLS]; ROWS];
 
+// The starting position, Black (1) on top and White (-1) below; exported to
+// Python as START_BOARD
+const START_BOARD: Board = [
+    [1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 1, 0, 0, 0, 0, 0, 1, 0],
+    [0, 0, 1, 0, 0, 0, 1, 0, 0],
+    [0, 0, 0, 1, 0, 1, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, -1, 0, -1, 0, 0, 0],
+    [0, 0, -1, 0, 0, 0, -1, 0, 0],
+    [0, -1, 0, 0, 0, 0, 0, -1, 0],
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1],
+];
+
+type Move = (usize, usize, usize, usize);
+
 #[pyclass]
 struct FiancoAI {
-    tt: TranspositionTable,
-    zobrist_table: Vec<Vec<[u64; 2]>>, // [ROWS][COLS][2]
-    current_hash_key: u64,
-    hash_history: Vec<u64>,
-    ai_player: i8,
-    root_move_scores: HashMap<(usize, usize, usize, usize), i32>,
+    alpha_beta: AlphaBeta,
+    mcts: Mcts,
+    algorithm: Algorithm,
+    skill: Skill,
+    book: Option<OpeningBook>,
+    book_depth: usize,
+    book_rng: StdRng,
+    last_stats: Option<SearchStats>,
+    last_iterations: Vec<SearchStats>,
 }
 
 #[pymethods]
 impl FiancoAI {
     #[new]
-    fn new(ai_player: i8) -> Self {
-        // Initialize the zobrist_table with random numbers
-        let mut rng = StdRng::seed_from_u64(0);
-        let mut zobrist_table: Vec<Vec<[u64; 2]>> = vec![vec![[0u64; 2]; COLS]; ROWS]; // [ROWS][COLS][2]
-        for i in 0..ROWS {
-            for j in 0..COLS {
-                for k in 0..2 {
-                    zobrist_table[i][j][k] = rng.gen::<u64>();
-                }
-            }
-        }
-
-        FiancoAI {
-            tt: HashMap::new(),
-            zobrist_table,
-            current_hash_key: 0,
-            hash_history: Vec::new(),
-            ai_player: ai_player,
-            root_move_scores: HashMap::new(),
-        }
+    #[args(threads = "1", algorithm = "\"alphabeta\"")]
+    fn new(ai_player: i8, threads: usize, algorithm: &str) -> PyResult<Self> {
+        let alpha_beta = AlphaBeta::new(ai_player, threads);
+        let mut mcts = Mcts::new();
+        mcts.stop = alpha_beta.stop.clone();
+        mcts.info = alpha_beta.info.clone();
+        Ok(FiancoAI {
+            alpha_beta,
+            mcts,
+            algorithm: parse_algorithm(algorithm)?,
+            skill: Skill::new(),
+            book: None,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            book_rng: StdRng::from_entropy(),
+            last_stats: None,
+            last_iterations: Vec::new(),
+        })
     }
 
+    /// Searches with the GIL released, so other Python threads (e.g. the
+    /// GUI loop) keep running and can end the search through a
+    /// `StopHandle`.
+    ///
+    /// `max_time` is the time for this move in seconds. Under a game clock,
+    /// pass `remaining_ms` (with the `increment_ms` per move and the
+    /// `moves_to_go` to the next time control, 0 for sudden death) and the
+    /// move is budgeted from the clock instead, never using all of it.
+    ///
+    /// With an opening book loaded, positions in the book are answered from
+    /// it without searching (score 0) while the game history is shorter
+    /// than the book depth.
+    #[args(remaining_ms = "None", increment_ms = "0", moves_to_go = "0")]
+    #[allow(clippy::too_many_arguments)]
     fn get_best_move(
         &mut self,
-        _py: Python,
+        py: Python,
         board: &PyArray2<i8>,
         player: i8,
         max_depth: i32,
-        max_time: u64,
+        max_time: f64,
+        remaining_ms: Option<u64>,
+        increment_ms: u64,
+        moves_to_go: u64,
     ) -> PyResult<(i32, Vec<(usize, usize, usize, usize)>)> {
         // Safely access the board data
         let board_readonly = board.readonly();
         let board_state = board_readonly.as_array();
-        let mut best_score= 505;
-        let mut pv = Vec::new();
-        let mut depth_results: Vec<(i32, i32, Vec<(usize, usize, usize, usize)>)> = Vec::new();
 
-        let start_time = Instant::now();
-        let max_time = Duration::new(max_time, 0);
+        let time = match remaining_ms {
+            Some(remaining_ms) => TimeLimits::from_clock(remaining_ms, increment_ms, moves_to_go),
+            None => TimeLimits::fixed(seconds(max_time)),
+        };
+        let limits = SearchLimits::timed(max_depth, time);
 
         // Validate board shape
         if board_state.shape() != [ROWS, COLS] {
@@ -101,118 +151,258 @@ impl FiancoAI {
             ));
         }
 
-        if self.tt.len() >= MAX_TT_SIZE {
-            self.tt.clear();
-            println!("Transposition Table cleared.");
-        }
-
         // Convert the ndarray to Vec<Vec<i8>>
-        let mut board_state: Board = pyarray_to_board(board)?;
+        let board_state: Board = pyarray_to_board(board)?;
 
         // Get valid moves
         let valid_moves = get_valid_moves(&board_state, player);
 
         if valid_moves.len() == 1 {
+            self.alpha_beta.stop_ponder();
             return Ok((
                 404, // Placeholder for evaluation
                 vec![valid_moves[0]],
             ));
         }
 
-        self.current_hash_key = self.compute_hash_key(&board_state);
+        if let Some(mv) = self.book_move(&board_state, player) {
+            return Ok((0, vec![mv]));
+        }
 
-        // Push the current hash key onto the stack
-        self.hash_history.push(self.current_hash_key);
+        // Return the best move and evaluation score if available
+        match self.run_search(py, &board_state, player, &limits) {
+            Some(result) => Ok((result.score, result.pv)),
+            None => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "No valid moves available for the AI.",
+            )),
+        }
+    }
 
-        // Initialize root_move_scores
-        self.root_move_scores.clear();
-        // self.tt.clear(); //ERASE THIS!!
+    /// Searches within a `SearchLimits` and returns `(score, pv, nodes)`.
+    /// With `deterministic=True` the result only depends on the position and
+    /// the limits, which makes it suitable for regression tests and bug
+    /// reports.
+    fn search(
+        &mut self,
+        py: Python,
+        board: &PyArray2<i8>,
+        player: i8,
+        limits: PyRef<PySearchLimits>,
+    ) -> PyResult<(i32, Vec<Move>, u64)> {
+        let board_state: Board = pyarray_to_board(board)?;
+        let limits = limits.to_limits();
+        match self.run_search(py, &board_state, player, &limits) {
+            Some(result) => Ok((result.score, result.pv, result.stats.nodes)),
+            None => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "No valid moves available for the AI.",
+            )),
+        }
+    }
 
-        for depth in 1..=max_depth {
+    /// Multi-PV analysis: searches the position and returns the best `lines`
+    /// root moves, best first, as `(score, pv, depth)`. Scores are from
+    /// White's point of view like in `get_best_move`; `depth` is the
+    /// iteration the line comes from (the line length for MCTS).
+    ///
+    /// `include` restricts the search to the given root moves (e.g. to score
+    /// a single move) and `exclude` rules moves out.
+    #[args(lines = "3", include = "None", exclude = "None")]
+    #[allow(clippy::too_many_arguments)]
+    fn analyse(
+        &mut self,
+        py: Python,
+        board: &PyArray2<i8>,
+        player: i8,
+        max_depth: i32,
+        max_time: f64,
+        lines: usize,
+        include: Option<Vec<Move>>,
+        exclude: Option<Vec<Move>>,
+    ) -> PyResult<Vec<(i32, Vec<Move>, i32)>> {
+        let board_state: Board = pyarray_to_board(board)?;
+        let limits = SearchLimits::timed(max_depth, TimeLimits::fixed(seconds(max_time)));
+
+        let root_filter = RootFilter {
+            include: include.unwrap_or_default(),
+            exclude: exclude.unwrap_or_default(),
+        };
+        let valid_moves = get_valid_moves(&board_state, player);
+        if let Some(mv) = root_filter.include.iter().chain(&root_filter.exclude).find(|mv| !valid_moves.contains(mv)) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Move {:?} is not legal in this position.", mv),
+            ));
+        }
+        if !valid_moves.iter().any(|mv| root_filter.allows(mv)) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "No root moves left to search.",
+            ));
+        }
 
-            if start_time.elapsed() >= max_time {
-                println!("Time limit reached. Breaking out of the search loop.");
-                break;
-            }
+        self.alpha_beta.multi_pv = lines.max(1);
+        self.mcts.multi_pv = lines.max(1);
+        self.alpha_beta.root_filter = root_filter.clone();
+        self.mcts.root_filter = root_filter;
+        let result = self.run_search(py, &board_state, player, &limits);
+        self.alpha_beta.multi_pv = 1;
+        self.mcts.multi_pv = 1;
+        self.alpha_beta.root_filter = RootFilter::default();
+        self.mcts.root_filter = RootFilter::default();
+
+        match result {
+            Some(result) => Ok(result.lines.into_iter().map(|line| (line.score, line.pv, line.depth)).collect()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "No valid moves available for the AI.",
+            )),
+        }
+    }
 
-            // Copy the current hash key
-            let mut hash_key = self.current_hash_key;
-
-            
-            // println!("hash_history before negamax: {:?}", self.hash_history);
-
-            // Call the Negamax algorithm with the Transposition Table
-            let result = self.negamax(
-                &mut board_state,
-                depth,
-                player,
-                MIN_SCORE,
-                MAX_SCORE,
-                &mut hash_key,
-                true,
-                &start_time,
-                max_time,
-            );
-        
-            match result {
-                Ok((score, pv_current)) => {
-                    best_score = -player as i32 * score;
-                    pv = pv_current.clone();
-                    depth_results.push((depth, best_score, pv_current.clone()));
-                    println!("Depth {}: Best Score = {}, PV = {:?}", depth, best_score, pv);
-                },
-                Err(_) => {
-                    // Time limit reached during negamax; break out of the loop
-                    println!("Time limit reached during negamax. Breaking out of the search loop.");
-                    break;
-                },
-            }
-            
+    /// Statistics of the last search as a `SearchStats`, or None before the
+    /// first one.
+    fn get_stats(&self) -> Option<PySearchStats> {
+        self.last_stats.as_ref().map(PySearchStats::from)
+    }
 
-        // println!("hash_history after negamax: {:?}", self.hash_history);
-        }
-        // println!("Time: {}", start_time.elapsed().as_secs_f64());
+    /// Statistics at the end of each completed iteration of the last
+    /// search, shallowest first (alpha-beta only).
+    fn get_iteration_stats(&self) -> Vec<PySearchStats> {
+        self.last_iterations.iter().map(PySearchStats::from).collect()
+    }
 
-        //** Just after finishing iterative deepening... **
+    /// Handle that polls this engine's progress reports from another thread
+    /// (e.g. the GUI loop) while a search runs.
+    fn get_info_handle(&self) -> InfoHandle {
+        InfoHandle { info: self.alpha_beta.info.clone() }
+    }
 
-        let loss_in_sight = depth_results.iter().any(|&(_, score, _)| player as i32 * score >= WIN_BY_TRIANGLE);
-        let max_score_achieved = depth_results.iter().any(|&(_, score, _)| score == -player as i32 * MAX_SCORE);
+    /// Prints search progress to stdout (off by default).
+    fn set_verbose(&mut self, verbose: bool) {
+        self.alpha_beta.verbose = verbose;
+        self.mcts.verbose = verbose;
+    }
 
+    /// Handle that stops this engine's searches from another thread or a UI
+    /// event. The stopped search returns the best move of its last completed
+    /// iteration right away.
+    fn get_stop_handle(&self) -> StopHandle {
+        StopHandle { stop: self.alpha_beta.stop.clone() }
+    }
 
-        let mut best_pv = None;
-        let mut pv_last_iter = Vec::new();
-        for (_, score, pv_candidate) in depth_results.iter().rev() {
-            if loss_in_sight {
-                if player as i32 * *score < WIN_BY_TRIANGLE {
-                    best_pv = Some(pv_candidate.clone());
-                    break;
-                }
-            }
-            if max_score_achieved && !loss_in_sight {
-                best_pv = Some(pv_candidate.clone());
-                if *score != -player as i32 * MAX_SCORE {
-                    if !pv_last_iter.is_empty() {
-                        best_pv = Some(pv_last_iter.clone());
-                    }
-                    break;
-                }
-                pv_last_iter = pv_candidate.clone();
-            }
+    /// Starts pondering after the engine's own move: `board` is the position
+    /// the engine just searched for `player`, `our_move` the move it plays
+    /// and `ponder_move` the expected reply (the second move of the PV).
+    /// The next `get_best_move` on the resulting position continues this
+    /// search under its own depth and time limits (a ponderhit), as does
+    /// `analyse` with the same number of `lines`, provided the game history
+    /// then also includes `board` and the position after `our_move`; any
+    /// other position cancels it. Returns False when there is nothing to
+    /// ponder (MCTS, illegal moves or game over).
+    #[args(lines = "1")]
+    fn start_ponder(
+        &mut self,
+        board: &PyArray2<i8>,
+        player: i8,
+        our_move: Move,
+        ponder_move: Move,
+        max_depth: i32,
+        lines: usize,
+    ) -> PyResult<bool> {
+        let searched: Board = pyarray_to_board(board)?;
+        if self.algorithm != Algorithm::AlphaBeta || !get_valid_moves(&searched, player).contains(&our_move) {
+            return Ok(false);
         }
-        if let Some(best_pv) = best_pv {
-            // Update pv to avoid the move leading to a forced loss
-            pv = best_pv;
-        } else {
-            // All depths resulted in MIN_SCORE or MAX_SCORE; pv remains as is
+        let mut after_our_move = searched;
+        apply_move(&mut after_our_move, player, our_move);
+        if is_game_over(&after_our_move, -player) || !get_valid_moves(&after_our_move, -player).contains(&ponder_move) {
+            return Ok(false);
         }
-        // Return the best move and evaluation score if available
-        if let Some(&(_from_row, _from_col, _to_row, _to_col)) = pv.first() {
-            Ok((best_score, pv))
-        } else {
-            Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-                "No valid moves available for the AI.",
-            ))
+        let mut board_state = after_our_move;
+        apply_move(&mut board_state, -player, ponder_move);
+        if is_game_over(&board_state, player) {
+            return Ok(false);
+        }
+        self.alpha_beta.start_ponder(&board_state, player, &[searched, after_our_move], max_depth, lines.max(1));
+        Ok(true)
+    }
+
+    /// Sets the positions played so far, oldest first and without the
+    /// current one, so the search counts repetitions like the real game
+    /// (threefold repetition is a draw). The history stays in effect until
+    /// it is set again or `new_game` is called.
+    fn set_history(&mut self, positions: Vec<&PyArray2<i8>>) -> PyResult<()> {
+        let positions = positions.into_iter().map(pyarray_to_board).collect::<PyResult<Vec<Board>>>()?;
+        self.alpha_beta.set_game_history(&positions);
+        Ok(())
+    }
+
+    /// Sets the game history from the moves played from `board`, with
+    /// `player` (-1 White, 1 Black) to move first. Every move must be legal;
+    /// the search should then be called on the position after the last one.
+    fn set_history_moves(&mut self, board: &PyArray2<i8>, player: i8, moves: Vec<Move>) -> PyResult<()> {
+        let mut board_state: Board = pyarray_to_board(board)?;
+        let mut player = player;
+        let mut positions = Vec::with_capacity(moves.len());
+        for mv in moves {
+            if is_game_over(&board_state, player) || !get_valid_moves(&board_state, player).contains(&mv) {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    format!("Move {:?} is not legal after {} moves.", mv, positions.len()),
+                ));
+            }
+            positions.push(board_state);
+            apply_move(&mut board_state, player, mv);
+            player = -player;
         }
+        self.alpha_beta.set_game_history(&positions);
+        Ok(())
+    }
+
+    /// Starts a new game: forgets the game history and cancels pondering.
+    fn new_game(&mut self) {
+        self.alpha_beta.new_game();
+    }
+
+    /// Cancels pondering, e.g. on undo or a new game. What it learned stays
+    /// in the transposition table.
+    fn stop_ponder(&mut self) {
+        self.alpha_beta.stop_ponder();
+    }
+
+    fn is_pondering(&self) -> bool {
+        self.alpha_beta.is_pondering()
+    }
+
+    /// Selects the search algorithm: "alphabeta" or "mcts".
+    fn set_algorithm(&mut self, algorithm: &str) -> PyResult<()> {
+        self.algorithm = parse_algorithm(algorithm)?;
+        Ok(())
+    }
+
+    fn get_algorithm(&self) -> &'static str {
+        self.algorithm.name()
+    }
+
+    /// Sets the number of search threads (1 = plain sequential search).
+    fn set_threads(&mut self, threads: usize) {
+        self.alpha_beta.threads = threads.max(1);
+    }
+
+    fn get_threads(&self) -> usize {
+        self.alpha_beta.threads
+    }
+
+    /// Configures the MCTS searcher: UCT exploration constant, playout
+    /// policy ("random" or "eval") and the playout budget per move.
+    #[args(exploration = "1.41", playout = "\"random\"", iterations = "50000")]
+    fn set_mcts_options(&mut self, exploration: f64, playout: &str, iterations: u32) -> PyResult<()> {
+        self.mcts.playout = Playout::from_name(playout).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown playout policy: {}", playout))
+        })?;
+        self.mcts.exploration = exploration;
+        self.mcts.iterations = iterations;
+        Ok(())
+    }
+
+    fn get_mcts_options(&self) -> (f64, &'static str, u32) {
+        (self.mcts.exploration, self.mcts.playout.name(), self.mcts.iterations)
     }
 
     // #[pyfunction]
@@ -238,276 +428,592 @@ impl FiancoAI {
 
     //     evaluate_board(&board_state, player)
     // }
-    #[pyo3(name = "get_tt_size")]
-    fn get_tt_size(&self) -> PyResult<usize> {
-        Ok(self.tt.len())
+    /// Configures how much the alpha-beta engine avoids draws, in
+    /// evaluation units (20 per stone): a draw counts `contempt` against the
+    /// engine and for its opponent, so positive values play for a win and
+    /// negative ones accept draws. `trend_percent` adds that share of the
+    /// engine's average root score over its last searches, and
+    /// `elo_advantage` (the engine's estimated Elo over the opponent, negative
+    /// against stronger ones) adds one unit per 10 Elo.
+    #[args(contempt = "30", trend_percent = "0", elo_advantage = "0")]
+    fn set_contempt(&mut self, contempt: i32, trend_percent: i32, elo_advantage: i32) {
+        self.alpha_beta.contempt.base = contempt;
+        self.alpha_beta.contempt.trend_percent = trend_percent;
+        self.alpha_beta.contempt.elo_advantage = elo_advantage;
     }
-}
 
-impl FiancoAI {
-    fn negamax(
-        &mut self,
-        board: &mut Board,
-        depth: i32,
-        player: i8,
-        mut alpha: i32,
-        mut beta: i32,
-        hash_key: &mut u64,
-        is_root: bool,
-        start_time: &Instant,
-        max_time: Duration,
-    ) -> Result<(i32, Vec<(usize, usize, usize, usize)>), ()> {
-        let key = *hash_key;
-        let old_alpha = alpha;
-        let mut old_best_move: Option<(usize, usize, usize, usize)> = None;
-
-        // Push the current hash key onto the stack
-        // self.hash_history.push(key);
-
-        if start_time.elapsed() >= max_time {
-            return Err(());
-        }
+    /// Returns `(contempt, trend_percent, elo_advantage, effective)`, where
+    /// `effective` is the value the next search will use.
+    fn get_contempt(&self) -> (i32, i32, i32, i32) {
+        let contempt = &self.alpha_beta.contempt;
+        (contempt.base, contempt.trend_percent, contempt.elo_advantage, contempt.value(true))
+    }
 
-        // Count how many times the current position has occurred in the current path
-        let repetitions = self.hash_history.iter().filter(|&&k| k == key).count();
-
-        // Check for threefold repetition
-        if repetitions >= 3 {
-            // self.hash_history.pop(); // Remove the hash key before returning
-            // println!("Popped hash key due to threefold repetition");
-            // println!("Threefold repetition detected.");
-            return Ok((-self.ai_player as i32 * DRAW_SCORE, Vec::new())); // Return a score indicating a draw
-        } else if repetitions == 0{
-            // Transposition Table lookup
-            if let Some(entry) = self.tt.get(&key) {
-                if entry.depth >= depth {
-                    match entry.flag {
-                        TTFlag::Exact => {
-                            let mut pv = Vec::new();
-                            if let Some(best_move) = entry.best_move {
-                                pv.push(best_move);
-                            }
-                            // self.hash_history.pop(); // Remove the hash key before returning
-                            // println!("Popped hash key due to TT Exact");
-                            return Ok((entry.eval, pv));
-                        },
-                        TTFlag::LowerBound => alpha = max(alpha, entry.eval),
-                        TTFlag::UpperBound => beta = min(beta, entry.eval),
-                    }
-                    if alpha >= beta {
-                        // self.hash_history.pop(); // Remove the hash key before returning
-                        // println!("Popped hash key due to TT alpha >= beta");
-                        return Ok((entry.eval, Vec::new()));
-                    }
-                }
-                if entry.best_move.is_some() {
-                    old_best_move = entry.best_move;
-                }
-            }
+    /// Sets evaluation weights by name (`stone`, `advance`, `edge` and
+    /// `race_win`, see the README), e.g. `{"stone": 24, "edge": 1}`, for
+    /// both engines of this instance; weights left out keep their value.
+    /// Raises ValueError on an unknown name, changing nothing.
+    fn set_eval_params(&mut self, params: HashMap<String, i32>) -> PyResult<()> {
+        let mut eval = self.alpha_beta.eval;
+        for (name, value) in &params {
+            eval.set(name, *value).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
         }
+        self.set_eval(eval);
+        Ok(())
+    }
 
-        // Check for depth or game over
-        if depth == 0 || is_game_over(board, player) {
-            let eval = -player as i32 * evaluate_board(board, player);
-            // self.hash_history.pop(); // Remove the hash key before returning
-            return Ok((eval, Vec::new()));
-        }
+    /// The evaluation weights in use, by name.
+    fn get_eval_params(&self) -> HashMap<&'static str, i32> {
+        self.alpha_beta.eval.values().into_iter().collect()
+    }
 
-        let mut max_eval = -std::i32::MAX;
-        let mut best_pv = Vec::new();
+    /// Loads evaluation weights from a text file of `name = value` lines,
+    /// with `#` comments; weights the file leaves out get their default.
+    fn load_eval_params(&mut self, path: &str) -> PyResult<()> {
+        let eval = EvalParams::load(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not load evaluation weights: {}", e))
+        })?;
+        self.set_eval(eval);
+        Ok(())
+    }
 
-        // Get valid moves
-        let mut moves = get_valid_moves(board, player);
-
-        if is_root {
-            // Sort moves based on root_move_scores
-            moves.sort_by_cached_key(|&m| {
-                // Use negative scores to sort in descending order
-                player as i32 * (self.root_move_scores.get(&m).cloned().unwrap_or(0))
-            });
-        } else {
-            // At non-root nodes, optionally use TT best move
-            if let Some(best_move_from_tt) = old_best_move {
-                if let Some(pos) = moves.iter().position(|&m| m == best_move_from_tt) {
-                    moves.swap(0, pos); // Move the best_move to the front
-                }
-            }
-        }
+    /// Writes the evaluation weights in use in the format `load_eval_params`
+    /// reads.
+    fn save_eval_params(&self, path: &str) -> PyResult<()> {
+        self.alpha_beta.eval.save(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not save evaluation weights: {}", e))
+        })
+    }
 
-        // Iterate over the moves
-        for m in moves {
-            // Make the move and update hash key
-            let capture = self.make_move(board, player, m, hash_key);
-
-            let new_depth = if capture { depth } else { depth - 1 };
-
-
-            // Recursive call...
-
-
-
-            let result = self.negamax(
-                board,
-                new_depth,
-                -player,
-                -beta,
-                -alpha,
-                hash_key,
-                false,
-                start_time,
-                max_time,
-            );
-        
-            // Undo the move and restore hash key
-            self.undo_move(board, player, m, capture, hash_key);
-        
-            match result {
-                Ok((eval, pv)) => {
-                    let eval = -eval;
-        
-                    if eval > max_eval {
-                        max_eval = eval;
-                        best_pv = pv;
-                        best_pv.insert(0, m); // Prepend the current move to the PV
-                    }
-                    alpha = max(alpha, eval);
-                    if alpha >= beta {
-                        break; // Beta cutoff
-                    }
-                },
-                Err(_) => {
-                    // Time limit reached during recursive call
-                    return Err(());
-                },
-            }
-        }
+    /// Sets the playing strength from 0 (weakest) to 20 (full strength,
+    /// the default). Below 20 the search depth is capped, some moves come
+    /// from a much shallower search, and the move is picked at random among
+    /// the best few root moves, favouring the better ones less as the level
+    /// drops. Applies to every search, including `analyse`.
+    fn set_skill_level(&mut self, level: u32) {
+        self.skill.level = level.min(MAX_SKILL);
+    }
+
+    /// Sets the skill level whose Elo target is closest to `elo`: level 0
+    /// aims at 800 and each level adds 75, up to 2300 at full strength.
+    /// The targets are a scale to calibrate against in arena games, not
+    /// measured ratings.
+    fn set_elo(&mut self, elo: i32) {
+        self.skill.level = Skill::level_for_elo(elo);
+    }
+
+    /// Returns `(level, elo)`, the skill level and its Elo target.
+    fn get_skill(&self) -> (u32, i32) {
+        (self.skill.level, self.skill.elo())
+    }
+
+    /// Loads an opening book built with `BookBuilder`.
+    fn load_book(&mut self, path: &str) -> PyResult<()> {
+        let book = OpeningBook::load(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not load opening book: {}", e))
+        })?;
+        self.book = Some(book);
+        Ok(())
+    }
+
+    /// Sets how many plies into the game, counted by the game history, the
+    /// book is used (0 disables it; 16 by default).
+    fn set_book_depth(&mut self, plies: usize) {
+        self.book_depth = plies;
+    }
 
+    fn get_book_depth(&self) -> usize {
+        self.book_depth
+    }
+
+    /// The move `get_best_move` would take from the book in this position,
+    /// drawn at random by weight, or None when the book has no legal move
+    /// for it or the game is past the book depth. For callers that search
+    /// with `analyse`.
+    fn get_book_move(&mut self, board: &PyArray2<i8>, player: i8) -> PyResult<Option<Move>> {
+        let board_state: Board = pyarray_to_board(board)?;
+        Ok(self.book_move(&board_state, player))
+    }
 
-        // Determine the flag for the transposition table entry
-        let flag = if max_eval <= old_alpha {
-            TTFlag::UpperBound
-        } else if max_eval >= beta {
-            TTFlag::LowerBound
-        } else {
-            TTFlag::Exact
+    /// Returns the book moves of the position as `(move, games, score)`,
+    /// most played first, where `score` counts the half-points the side that
+    /// played the move scored. Empty without a book.
+    fn probe_book(&self, board: &PyArray2<i8>, player: i8) -> PyResult<Vec<(Move, u32, u32)>> {
+        let board_state: Board = pyarray_to_board(board)?;
+        let moves = match &self.book {
+            Some(book) => book.probe(&board_state, player),
+            None => Vec::new(),
         };
+        Ok(moves.into_iter().map(|mv| (mv.mv, mv.games, mv.score)).collect())
+    }
 
-        // Store the evaluation in the transposition table
-        let entry = TTEntry {
-            best_move: if best_pv.is_empty() { None } else { Some(best_pv[0]) },
-            eval: max_eval,
-            depth,
-            flag,
+    /// Configures the proof-number endgame step of the alpha-beta search:
+    /// positions with at most `max_stones` stones are handed to the solver
+    /// first (0 disables it), using up to `memory_mb` megabytes of nodes.
+    fn set_solver_options(&mut self, max_stones: usize, memory_mb: usize) {
+        self.alpha_beta.solver_max_stones = max_stones;
+        self.alpha_beta.solver_memory_mb = memory_mb;
+    }
+
+    /// Loads an endgame tablebase built with `generate_tablebase`. The
+    /// alpha-beta search then plays covered positions perfectly and uses
+    /// exact values for them inside the tree.
+    fn load_tablebase(&mut self, path: &str) -> PyResult<()> {
+        let tablebase = Tablebase::load(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not load tablebase: {}", e))
+        })?;
+        self.alpha_beta.tablebase = Some(Arc::new(tablebase));
+        Ok(())
+    }
+
+    /// Probes the loaded tablebase. Returns `("win" | "loss" | "draw",
+    /// distance in plies)`, or None if the position is not covered.
+    fn probe_tablebase(&self, board: &PyArray2<i8>, player: i8) -> PyResult<Option<(&'static str, u8)>> {
+        let board_state: Board = pyarray_to_board(board)?;
+        let value = match &self.alpha_beta.tablebase {
+            Some(tablebase) => tablebase.probe(&board_state, player),
+            None => None,
         };
-        self.tt.insert(key, entry);
+        Ok(value.map(|value| match value {
+            TbValue::Win(d) => ("win", d),
+            TbValue::Loss(d) => ("loss", d),
+            TbValue::Draw => ("draw", 0),
+        }))
+    }
 
-        if is_root && !best_pv.is_empty() {
-            // At root, store the move's score for ordering
-            self.root_move_scores.insert(best_pv[0], max_eval);
-        }
+    /// Resizes the transposition table to at most `megabytes` MB (128 by
+    /// default), rounded down to a power of two. The table is allocated
+    /// once and emptied by resizing; pondering stops.
+    fn set_hash_size(&mut self, megabytes: usize) {
+        self.alpha_beta.set_hash_size(megabytes);
+    }
 
-        Ok((max_eval, best_pv))
+    /// The transposition table size in megabytes.
+    fn get_hash_size(&self) -> usize {
+        self.alpha_beta.hash_size()
     }
-    fn compute_hash_key(&self, board: &Board) -> u64 {
-        let mut hash_key = 0u64;
-        for i in 0..ROWS {
-            for j in 0..COLS {
-                let piece = board[i][j];
-                if piece != 0 {
-                    let piece_index = if piece == -1 { 0 } else { 1 };
-                    hash_key ^= self.zobrist_table[i][j][piece_index];
-                }
-            }
-        }
-        hash_key
+
+    /// Empties the transposition table, e.g. for a new game. Within a game
+    /// there is no need: entries of earlier searches are replaced gradually
+    /// as new ones come in. Pondering stops.
+    fn clear_hash(&mut self) {
+        self.alpha_beta.clear_hash();
     }
-    fn make_move(
-        &mut self,
-        board: &mut Board,
-        player: i8,
-        mv: (usize, usize, usize, usize),
-        hash_key: &mut u64,
-    ) -> bool {
-        let (from_row, from_col, to_row, to_col) = mv;
 
-        let piece_index = if player == -1 { 0 } else { 1 };
+    /// Saves the transposition table entries searched at least `min_depth`
+    /// plies deep to a binary file, e.g. after analysing critical positions,
+    /// and returns how many were written. Files are versioned and readable
+    /// on any machine running the same version of the engine, into a table
+    /// no larger than the one that saved them.
+    #[args(min_depth = "0")]
+    fn save_hash(&self, path: &str, min_depth: i32) -> PyResult<usize> {
+        self.alpha_beta.save_hash(Path::new(path), min_depth).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not save transposition table: {}", e))
+        })
+    }
 
-        // XOR out the piece from its original position
-        *hash_key ^= self.zobrist_table[from_row][from_col][piece_index];
+    /// Adds the entries of a file written by `save_hash` to the
+    /// transposition table and returns how many were read. Entries already
+    /// in the table may be replaced. A file saved from a smaller table
+    /// raises an IOError, as it lacks key bits this one needs. Like other
+    /// entries of earlier searches, the loaded ones give way gradually to
+    /// new results, so load right before analysing the positions they cover.
+    fn load_hash(&self, path: &str) -> PyResult<usize> {
+        self.alpha_beta.load_hash(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not load transposition table: {}", e))
+        })
+    }
 
-        // Remove the piece from original position
-        board[from_row][from_col] = 0;
+    /// Number of transposition table slots in use.
+    #[pyo3(name = "get_tt_size")]
+    fn get_tt_size(&self) -> PyResult<usize> {
+        Ok(self.alpha_beta.tt_size())
+    }
+}
 
-        // XOR in the piece at the new position
-        *hash_key ^= self.zobrist_table[to_row][to_col][piece_index];
-        
-        // Push the current hash key onto the stack
-        self.hash_history.push(*hash_key);
-        // println!("hash_history after make_move: {:?}", self.hash_history);
+/// Collects opening statistics from game records and self-play for the first
+/// `max_plies` plies of each game, and saves them as an opening book.
+#[pyclass(name = "BookBuilder")]
+struct PyBookBuilder {
+    builder: BookBuilder,
+}
 
-        // Place the piece at new position
-        board[to_row][to_col] = player;
+#[pymethods]
+impl PyBookBuilder {
+    #[new]
+    #[args(max_plies = "16")]
+    fn new(max_plies: usize) -> Self {
+        PyBookBuilder { builder: BookBuilder::new(max_plies) }
+    }
 
-        let mut captured = false;
+    /// Adds a game played from `board` with `player` (-1 White, 1 Black) to
+    /// move first, as its list of moves, won by `winner` (-1, 1, or 0 for a
+    /// draw). Raises ValueError on an illegal move.
+    fn add_game(&mut self, board: &PyArray2<i8>, player: i8, moves: Vec<Move>, winner: i8) -> PyResult<()> {
+        let board_state: Board = pyarray_to_board(board)?;
+        self.builder.add_game(&board_state, player, &moves, winner).map_err(|index| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                format!("Move {:?} is not legal after {} moves.", moves[index], index),
+            )
+        })
+    }
 
-        // Check for capture
-        if (from_row as i32 - to_row as i32).abs() == 2 {
-            let captured_row = (from_row + to_row) / 2;
-            let captured_col = (from_col + to_col) / 2;
-            let captured_piece_index = if -player == -1 { 0 } else { 1 };
+    /// Plays `games` engine games from `board`, White to move, with `nodes`
+    /// nodes per move and adds them. Within the book plies the moves are
+    /// picked at random among the best few at `skill_level`, so the games
+    /// branch. Runs with the GIL released.
+    #[args(nodes = "20000", skill_level = "16")]
+    fn add_self_play(&mut self, py: Python, board: &PyArray2<i8>, games: usize, nodes: u64, skill_level: u32) -> PyResult<()> {
+        let board_state: Board = pyarray_to_board(board)?;
+        let builder = &mut self.builder;
+        py.allow_threads(|| builder.add_self_play(&board_state, games, nodes, skill_level));
+        Ok(())
+    }
 
-            // XOR out the captured piece
-            *hash_key ^= self.zobrist_table[captured_row][captured_col][captured_piece_index];
+    /// Number of distinct positions collected so far.
+    fn positions(&self) -> usize {
+        self.builder.positions()
+    }
 
-            // Remove the captured piece
-            board[captured_row][captured_col] = 0;
+    /// Writes the book of every move played at least `min_games` times and
+    /// returns the number of entries.
+    #[args(min_games = "1")]
+    fn save(&self, path: &str, min_games: u32) -> PyResult<usize> {
+        let book = self.builder.build(min_games);
+        book.save(Path::new(path)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Could not save opening book: {}", e))
+        })?;
+        Ok(book.len())
+    }
+}
 
-            captured = true;
-        }
+// Set by `stop_solver` to end the running `solve_position` calls early
+static SOLVER_STOP: AtomicBool = AtomicBool::new(false);
+
+/// Proof-number solver: proves the position a win, loss or draw for `player`.
+/// Returns `(result, move, distance, nodes)`, where `result` is "win",
+/// "loss", "draw" or "unknown" (limits reached or stopped), `move` is the
+/// proof move and `distance` the length in plies of the proof line found.
+/// The solver runs with the Python GIL released, so another thread can
+/// call `stop_solver` meanwhile.
+#[pyfunction(max_time = "10.0", memory_mb = "256")]
+fn solve_position(
+    py: Python,
+    board: &PyArray2<i8>,
+    player: i8,
+    max_time: f64,
+    memory_mb: usize,
+) -> PyResult<(&'static str, Option<Move>, Option<u32>, usize)> {
+    let board_state: Board = pyarray_to_board(board)?;
+    SOLVER_STOP.store(false, Ordering::Relaxed);
+    let result = py.allow_threads(|| {
+        pns::solve(
+            &board_state,
+            player,
+            Some(seconds(max_time)),
+            pns::nodes_for_memory(memory_mb),
+            &SOLVER_STOP,
+        )
+    });
+    Ok((result.proof.name(), result.best_move, result.distance, result.nodes))
+}
+
+/// Stops the `solve_position` calls running on other threads, which then
+/// return what they have proven so far ("unknown" unless already decided).
+#[pyfunction]
+fn stop_solver() {
+    SOLVER_STOP.store(true, Ordering::Relaxed);
+}
 
-        captured
+/// Generates the endgame tablebase for up to `max_stones` stones per side
+/// by retrograde analysis and writes it to `path`. At most two stones per
+/// side are supported, which take about 21 MB.
+#[pyfunction(max_stones = "2")]
+fn generate_tablebase(py: Python, path: &str, max_stones: usize) -> PyResult<()> {
+    if max_stones == 0 || max_stones > tablebase::MAX_TB_STONES {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "max_stones must be between 1 and {}",
+            tablebase::MAX_TB_STONES
+        )));
     }
-    fn undo_move(
-        &mut self,
-        board: &mut Board,
-        player: i8,
-        mv: (usize, usize, usize, usize),
-        captured: bool,
-        hash_key: &mut u64,
-    ) {
-        let (from_row, from_col, to_row, to_col) = mv;
+    let tablebase = py.allow_threads(|| Tablebase::generate(max_stones));
+    tablebase.save(Path::new(path))?;
+    Ok(())
+}
 
-        let piece_index = if player == -1 { 0 } else { 1 };
+/// Returns the Elo target of skill `level` (see `FiancoAI.set_skill_level`).
+#[pyfunction]
+fn skill_elo(level: u32) -> i32 {
+    Skill::elo_for_level(level)
+}
 
-        self.hash_history.pop(); // Remove the hash key before undoing the move
-        // println!("hash_history after undo_move: {:?}", self.hash_history);
+/// Stops the running search of the `FiancoAI` it came from. Calling
+/// `stop` when no search runs has no effect on the next one.
+#[pyclass]
+struct StopHandle {
+    stop: Arc<AtomicBool>,
+}
 
-        // XOR out the piece from the destination position
-        *hash_key ^= self.zobrist_table[to_row][to_col][piece_index];
+#[pymethods]
+impl StopHandle {
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
 
-        // Remove the piece from the destination position
-        board[to_row][to_col] = 0;
+/// Limits for `FiancoAI.search`. The search ends at `depth`, after
+/// `max_time` seconds (or the time budgeted from `remaining_ms`,
+/// `increment_ms` and `moves_to_go`, as in `get_best_move`) or after `nodes`
+/// nodes (MCTS playouts), whichever comes first; unset limits do not apply.
+/// `deterministic` searches on one thread from an empty transposition table
+/// and ignores the time limits, so only depth and nodes end the search.
+#[pyclass(name = "SearchLimits")]
+#[derive(Clone)]
+struct PySearchLimits {
+    #[pyo3(get, set)]
+    depth: i32,
+    #[pyo3(get, set)]
+    max_time: Option<f64>,
+    #[pyo3(get, set)]
+    nodes: Option<u64>,
+    #[pyo3(get, set)]
+    remaining_ms: Option<u64>,
+    #[pyo3(get, set)]
+    increment_ms: u64,
+    #[pyo3(get, set)]
+    moves_to_go: u64,
+    #[pyo3(get, set)]
+    deterministic: bool,
+}
 
-        // XOR in the piece at the original position
-        *hash_key ^= self.zobrist_table[from_row][from_col][piece_index];
+#[pymethods]
+impl PySearchLimits {
+    #[new]
+    #[args(
+        depth = "64",
+        max_time = "None",
+        nodes = "None",
+        remaining_ms = "None",
+        increment_ms = "0",
+        moves_to_go = "0",
+        deterministic = "false"
+    )]
+    fn new(
+        depth: i32,
+        max_time: Option<f64>,
+        nodes: Option<u64>,
+        remaining_ms: Option<u64>,
+        increment_ms: u64,
+        moves_to_go: u64,
+        deterministic: bool,
+    ) -> Self {
+        PySearchLimits { depth, max_time, nodes, remaining_ms, increment_ms, moves_to_go, deterministic }
+    }
+}
 
-        // Place the piece back at the original position
-        board[from_row][from_col] = player;
+impl PySearchLimits {
+    fn to_limits(&self) -> SearchLimits {
+        let time = match (self.remaining_ms, self.max_time) {
+            (Some(remaining_ms), _) => Some(TimeLimits::from_clock(remaining_ms, self.increment_ms, self.moves_to_go)),
+            (None, Some(max_time)) => Some(TimeLimits::fixed(seconds(max_time))),
+            (None, None) => None,
+        };
+        SearchLimits { max_depth: self.depth, time, nodes: self.nodes, deterministic: self.deterministic }
+    }
+}
 
-        if captured {
-            let captured_row = (from_row + to_row) / 2;
-            let captured_col = (from_col + to_col) / 2;
-            let captured_piece_index = if -player == -1 { 0 } else { 1 };
+/// Statistics of a search, or of the search up to the end of one iteration.
+/// `nodes` counts all threads (playouts for MCTS), `time` is in seconds,
+/// `seldepth` is the deepest ply reached including capture extensions,
+/// `tt_collisions` counts transposition table entries of other positions
+/// that probes met and rejected by their check bits, and `hashfull` is the
+/// share of the table in use, in permille.
+#[pyclass(name = "SearchStats")]
+#[derive(Clone)]
+struct PySearchStats {
+    #[pyo3(get)]
+    depth: i32,
+    #[pyo3(get)]
+    seldepth: i32,
+    #[pyo3(get)]
+    nodes: u64,
+    #[pyo3(get)]
+    nps: u64,
+    #[pyo3(get)]
+    time: f64,
+    #[pyo3(get)]
+    tt_probes: u64,
+    #[pyo3(get)]
+    tt_hits: u64,
+    #[pyo3(get)]
+    tt_collisions: u64,
+    #[pyo3(get)]
+    beta_cutoffs: u64,
+    #[pyo3(get)]
+    first_move_cutoffs: u64,
+    #[pyo3(get)]
+    first_move_cutoff_rate: f64,
+    #[pyo3(get)]
+    hashfull: u32,
+}
 
-            // XOR in the captured piece
-            *hash_key ^= self.zobrist_table[captured_row][captured_col][captured_piece_index];
+#[pymethods]
+impl PySearchStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchStats(depth={}, seldepth={}, nodes={}, nps={}, time={:.3}, tt_hits={}/{}, tt_collisions={}, cutoffs={} ({} or {:.0}% by the first move), hashfull={})",
+            self.depth,
+            self.seldepth,
+            self.nodes,
+            self.nps,
+            self.time,
+            self.tt_hits,
+            self.tt_probes,
+            self.tt_collisions,
+            self.beta_cutoffs,
+            self.first_move_cutoffs,
+            100.0 * self.first_move_cutoff_rate,
+            self.hashfull,
+        )
+    }
+}
 
-            // Restore the captured piece
-            board[captured_row][captured_col] = -player;
+impl From<&SearchStats> for PySearchStats {
+    fn from(stats: &SearchStats) -> Self {
+        PySearchStats {
+            depth: stats.depth,
+            seldepth: stats.seldepth,
+            nodes: stats.nodes,
+            nps: stats.nps(),
+            time: stats.time.as_secs_f64(),
+            tt_probes: stats.tt_probes,
+            tt_hits: stats.tt_hits,
+            tt_collisions: stats.tt_collisions,
+            beta_cutoffs: stats.beta_cutoffs,
+            first_move_cutoffs: stats.first_move_cutoffs,
+            first_move_cutoff_rate: stats.first_move_cutoff_rate(),
+            hashfull: stats.hashfull,
         }
     }
 }
 
+/// Progress reports of the `FiancoAI` it came from: one after each completed
+/// depth (one per line in multi-PV mode) and every half second in between.
+/// Each search starts with an empty queue.
+#[pyclass]
+struct InfoHandle {
+    info: Arc<InfoQueue>,
+}
+
+#[pymethods]
+impl InfoHandle {
+    /// Takes the reports sent since the last call, oldest first, as
+    /// `SearchInfo` objects.
+    fn poll(&self) -> Vec<PySearchInfo> {
+        self.info.drain().into_iter().map(PySearchInfo::from).collect()
+    }
+}
+
+/// Progress report of a running search: `depth` being searched, `multipv`
+/// (1 for the main line), `score` from White's point of view with its `pv`,
+/// and the `nodes` and `time` in seconds since the search started. Reports
+/// sent while a depth is still running carry the line of the previous one.
+#[pyclass(name = "SearchInfo")]
+struct PySearchInfo {
+    #[pyo3(get)]
+    depth: i32,
+    #[pyo3(get)]
+    multipv: usize,
+    #[pyo3(get)]
+    score: i32,
+    #[pyo3(get)]
+    pv: Vec<Move>,
+    #[pyo3(get)]
+    nodes: u64,
+    #[pyo3(get)]
+    time: f64,
+}
+
+#[pymethods]
+impl PySearchInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchInfo(depth={}, multipv={}, score={}, nodes={}, time={:.3}, pv={:?})",
+            self.depth, self.multipv, self.score, self.nodes, self.time, self.pv,
+        )
+    }
+}
+
+impl From<SearchInfo> for PySearchInfo {
+    fn from(info: SearchInfo) -> Self {
+        PySearchInfo {
+            depth: info.depth,
+            multipv: info.multipv,
+            score: info.score,
+            pv: info.pv,
+            nodes: info.nodes,
+            time: info.time.as_secs_f64(),
+        }
+    }
+}
+
+impl FiancoAI {
+    // Runs the selected searcher with the GIL released, weakened to the skill
+    // level, and keeps its statistics
+    fn run_search(&mut self, py: Python, board: &Board, player: i8, limits: &SearchLimits) -> Option<SearchResult> {
+        let limits = self.skill.limits(limits);
+        let lines = self.alpha_beta.multi_pv;
+        if !self.skill.is_full_strength() {
+            self.alpha_beta.multi_pv = lines.max(SKILL_LINES);
+            self.mcts.multi_pv = lines.max(SKILL_LINES);
+        }
+        let result = py.allow_threads(|| self.searcher().search(board, player, &limits));
+        self.alpha_beta.multi_pv = lines;
+        self.mcts.multi_pv = lines;
+        let result = result.map(|result| self.skill.choose(result, player, lines));
+        if let Some(result) = &result {
+            self.last_stats = Some(result.stats.clone());
+            self.last_iterations = result.iterations.clone();
+        }
+        result
+    }
+
+    // A weighted random book move, while the game is within the book depth.
+    // Pondering stops when there is one, as the search is skipped
+    fn book_move(&mut self, board: &Board, player: i8) -> Option<Move> {
+        let book = self.book.as_ref()?;
+        if self.alpha_beta.game_ply() >= self.book_depth {
+            return None;
+        }
+        let mv = book.choose(board, player, &mut self.book_rng)?;
+        self.alpha_beta.stop_ponder();
+        Some(mv)
+    }
+
+    // Both engines evaluate with the same weights
+    fn set_eval(&mut self, eval: EvalParams) {
+        self.alpha_beta.eval = eval;
+        self.mcts.eval = eval;
+    }
+
+    fn searcher(&mut self) -> &mut dyn Searcher {
+        match self.algorithm {
+            Algorithm::AlphaBeta => &mut self.alpha_beta,
+            Algorithm::Mcts => &mut self.mcts,
+        }
+    }
+}
+
+fn seconds(time: f64) -> Duration {
+    Duration::from_secs_f64(time.max(0.0))
+}
+
+fn parse_algorithm(name: &str) -> PyResult<Algorithm> {
+    Algorithm::from_name(name).ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown search algorithm: {}", name))
+    })
+}
+
 // fn board_to_key(board: &[Vec<i8>]) -> u64 {
 //     let mut hasher = DefaultHasher::new();
 //     board.hash(&mut hasher);
@@ -547,6 +1053,18 @@ fn get_valid_moves(board: &Board, player: i8) -> Vec<(usize, usize, usize, usize
     }
 }
 
+// Plays `mv` for `player` on the board and reports whether it was a capture
+fn apply_move(board: &mut Board, player: i8, mv: Move) -> bool {
+    let (from_row, from_col, to_row, to_col) = mv;
+    board[from_row][from_col] = 0;
+    board[to_row][to_col] = player;
+    if (from_row as i32 - to_row as i32).abs() == 2 {
+        board[(from_row + to_row) / 2][(from_col + to_col) / 2] = 0;
+        return true;
+    }
+    false
+}
+
 fn get_possible_captures(
     board: &Board,
     player: i8,
@@ -631,132 +1149,46 @@ fn get_all_possible_moves(
 }
 
 #[inline]
-fn evaluate_board(board: &Board, player_to_move: i8) -> i32 {
+fn evaluate_board(board: &Board, player_to_move: i8, params: &EvalParams) -> i32 {
     // if is_game_over(board, 1) {
     //     return MAX_SCORE;
     // } 
     if is_game_over(board, player_to_move) {
         return player_to_move as i32 * MAX_SCORE;
     }
-    // Calculate the score based on the maximizer's perspective
+    // A proven race outweighs everything else; the sooner the win, the better
+    if let Some(race) = race::solve(board, player_to_move) {
+        return -(race.winner as i32) * (params.race_win - race.plies as i32);
+    }
+    evaluate_stones(board, params)
+}
+
+// Material and advancement, from the maximizer's perspective
+#[inline]
+fn evaluate_stones(board: &Board, params: &EvalParams) -> i32 {
     let mut score = 0;
-    let mut length_triangle_white: usize = ROWS;
-    let mut length_triangle_black: usize = ROWS;
-    let mut triangle_found = false;
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            match board[i][j] {
+    for (i, row) in board.iter().enumerate() {
+        for (j, &piece) in row.iter().enumerate() {
+            match piece {
                 -1 => {
-                    if triangle_to_win(board, player_to_move,-1, i, j) {
-                        length_triangle_white = std::cmp::min(length_triangle_white, i);
-                        triangle_found = true;
-                        continue;
-                    }
-                    if !triangle_found {
-                        score += 20;
-                        score += 3 * (ROWS - i - 1) as i32;
-                        score += 2 * (j as i32 - 4).abs();
-                    }
+                    score += params.stone;
+                    score += params.advance * (ROWS - i - 1) as i32;
+                    score += params.edge * (j as i32 - 4).abs();
                 },
                 1 => {
-                    if triangle_to_win(board, player_to_move, 1, i, j) {
-                        length_triangle_black = std::cmp::min(length_triangle_black, ROWS - i - 1 as usize);
-                        triangle_found = true;
-                        continue;
-                    }
-                    if !triangle_found {
-                        score -= 20;
-                        score -= 3 * i as i32;
-                        score -= 2 * (j as i32 - 4).abs();
-                    }
+                    score -= params.stone;
+                    score -= params.advance * i as i32;
+                    score -= params.edge * (j as i32 - 4).abs();
                 },
                 _ => (),
             }
         }
     }
-
-    // ** Rest of the code ** 
-
-    if length_triangle_black != ROWS || length_triangle_white != ROWS {
-        return (2 * (length_triangle_black - length_triangle_white) as i32 - player_to_move as i32) * WIN_BY_TRIANGLE;
-    }
     score
 }
 
-// fn triangle_to_win(board: &[Vec<i8>], player: i8, i: usize, j: usize) -> bool {
-//     let i = i as isize;
-//     let j = j as isize;
-//     let rows = ROWS as isize;
-//     let cols = COLS as isize;
-
-//     let (i1, i2) = if player == 1 {
-//         (i + 1, rows - 1)
-//     } else {
-//         // if i == 0 {
-//         //     println!("i is zero: {}", i);
-//         // }
-//         (0, if i > 0 { i - 1 } else { 0 })
-//     };
-
-//     for n in i1..=i2 {
-//         let delta = player as isize * (n - i ) - 1;
-//         let j1 = (j - delta).max(0).min(cols - 1);
-//         let j2 = (j + delta).max(0).min(cols - 1);
-//         // if j2 < 0 || j1 < 0 {
-//         //     println!("j1 or j2 is less than zero: {}, {}", j1, j2);
-//         // }
-//         for m in j1..=j2 {
-//             if board[n as usize][m as usize] == -player {
-//                 return false;
-//             }
-//         }
-//     }
-//     true
-// }
-
-#[inline]
-fn triangle_to_win(board: &Board, player_to_move: i8, player: i8, i: usize, j: usize) -> bool {
-    // Convert indices to i32 for calculations
-    let i = i as i32;
-    let j = j as i32;
-    let rows = ROWS as i32;
-    let cols = COLS as i32;
-    let player_i32 = player as i32; // Cache player as i32
-
-    // Determine the step direction based on the player
-    let step_n = -player_i32; // 1 for player 1, -1 for player -1
-
-    // Determine starting and ending row indices based on player
-    let (end_n, start_n) = if player == 1 {
-        (i + 1, rows - 1)
-    } else {
-        (i - 1, 0)
-    };
-
-    let mut n = start_n;
-    let delta_offset = if player_to_move == player {1} else {0}; // Used to adjust delta after the first iteration
-
-    loop {
-        let delta = player_i32 * (n - i) - delta_offset;
-        let j1 = (j - delta).max(0).min(cols - 1);
-        let j2 = (j + delta).max(0).min(cols - 1);
-
-        // Check the triangle row for opponent's pieces
-        for m in j1..=j2 {
-            if board[n as usize][m as usize] == -player {
-                return false;
-            }
-        }
-
-        // Break the loop if we've reached the end
-        if n == end_n {
-            break;
-        }
-
-        // Move to the next row in the triangle
-        n += step_n;
-    }
-    true
+fn count_stones(board: &Board) -> usize {
+    board.iter().flatten().filter(|&&piece| piece != 0).count()
 }
 
 fn is_game_over(board: &Board, player: i8) -> bool {
@@ -782,5 +1214,16 @@ fn is_winner(board: &Board, player: i8) -> bool {
 #[pymodule]
 fn fianco_brain(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<FiancoAI>()?;
+    m.add_class::<StopHandle>()?;
+    m.add_class::<InfoHandle>()?;
+    m.add_class::<PyBookBuilder>()?;
+    m.add_class::<PySearchLimits>()?;
+    m.add_class::<PySearchStats>()?;
+    m.add_class::<PySearchInfo>()?;
+    m.add_function(wrap_pyfunction!(solve_position, m)?)?;
+    m.add_function(wrap_pyfunction!(stop_solver, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_tablebase, m)?)?;
+    m.add_function(wrap_pyfunction!(skill_elo, m)?)?;
+    m.add("START_BOARD", START_BOARD.iter().map(|row| row.to_vec()).collect::<Vec<_>>())?;
     Ok(())
 }
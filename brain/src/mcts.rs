@@ -0,0 +1,292 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::eval::EvalParams;
+use crate::info::{InfoQueue, SearchInfo, INFO_INTERVAL};
+use crate::searcher::{PvLine, RootFilter, SearchLimits, SearchResult, SearchStats, Searcher};
+use crate::{apply_move, evaluate_board, get_valid_moves, is_winner, Board, Move};
+
+const DEFAULT_EXPLORATION: f64 = 1.41;
+const DEFAULT_ITERATIONS: u32 = 50_000;
+const MAX_PLAYOUT_PLIES: usize = 200; // Random playouts longer than this count as a draw
+const EVAL_PLAYOUT_PLIES: usize = 12; // Eval-guided playouts are cut here and scored by the evaluation
+const EVAL_SCALE: f64 = 100.0; // Evaluation difference that maps to ~73% win probability
+const GREEDY_EPSILON: f64 = 0.1; // Chance of a random move in eval-guided playouts
+const SCORE_SCALE: f64 = 1000.0; // Reported score for a certain win
+const ITERATIONS_PER_INFO_CHECK: u64 = 256; // The clock is read for progress reports only this often (a power of two)
+
+/// How the simulation step picks its moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Playout {
+    Random,
+    EvalGuided,
+}
+
+impl Playout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "random" => Some(Playout::Random),
+            "eval" | "eval-guided" | "eval_guided" => Some(Playout::EvalGuided),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Playout::Random => "random",
+            Playout::EvalGuided => "eval",
+        }
+    }
+}
+
+struct Node {
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    player: i8, // Side to move at this node
+    visits: u32,
+    reward: f64, // Summed results for the player who played `mv`
+}
+
+impl Node {
+    fn new(mv: Option<Move>, parent: Option<usize>, board: &Board, player: i8) -> Self {
+        let untried = if is_winner(board, -player) {
+            Vec::new()
+        } else {
+            get_valid_moves(board, player)
+        };
+        Node {
+            mv,
+            parent,
+            children: Vec::new(),
+            untried,
+            player,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+}
+
+/// Monte Carlo Tree Search with UCT selection. The tree is rebuilt on every
+/// call; `max_depth` is ignored and the search runs until `iterations`
+/// playouts (or the node budget, if lower) are done or the soft time limit
+/// passes. A deterministic search restarts the random generator from its
+/// fixed seed.
+pub(crate) struct Mcts {
+    pub exploration: f64,
+    pub playout: Playout,
+    pub iterations: u32,
+    pub multi_pv: usize,
+    pub root_filter: RootFilter,
+    pub eval: EvalParams, // Weights of eval-guided playouts
+    pub verbose: bool, // Print progress to stdout
+    pub info: Arc<InfoQueue>, // Progress reports for Python to poll
+    pub stop: Arc<AtomicBool>, // Set from outside to end the current search early
+    rng: StdRng,
+}
+
+impl Mcts {
+    pub fn new() -> Self {
+        Mcts {
+            exploration: DEFAULT_EXPLORATION,
+            playout: Playout::Random,
+            iterations: DEFAULT_ITERATIONS,
+            multi_pv: 1,
+            root_filter: RootFilter::default(),
+            eval: EvalParams::default(),
+            verbose: false,
+            info: Arc::new(InfoQueue::default()),
+            stop: Arc::new(AtomicBool::new(false)),
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    fn select_child(&self, nodes: &[Node], node: usize) -> usize {
+        let log_visits = (nodes[node].visits as f64).ln();
+        let uct = |child: &Node| {
+            child.reward / child.visits as f64
+                + self.exploration * (log_visits / child.visits as f64).sqrt()
+        };
+        *nodes[node]
+            .children
+            .iter()
+            .max_by(|&&a, &&b| uct(&nodes[a]).total_cmp(&uct(&nodes[b])))
+            .expect("fully expanded node without children")
+    }
+
+    /// Plays the position out and returns the result for `player`, the side
+    /// to move on `board`: 1 for a win, 0 for a loss and 0.5 for a draw.
+    fn simulate(&mut self, board: &mut Board, player: i8) -> f64 {
+        let max_plies = match self.playout {
+            Playout::Random => MAX_PLAYOUT_PLIES,
+            Playout::EvalGuided => EVAL_PLAYOUT_PLIES,
+        };
+        let mut to_move = player;
+        for _ in 0..max_plies {
+            let moves = if is_winner(board, -to_move) {
+                Vec::new()
+            } else {
+                get_valid_moves(board, to_move)
+            };
+            if moves.is_empty() {
+                return if to_move == player { 0.0 } else { 1.0 };
+            }
+            let mv = match self.playout {
+                Playout::Random => moves[self.rng.gen_range(0..moves.len())],
+                Playout::EvalGuided => self.greedy_move(board, to_move, &moves),
+            };
+            apply_move(board, to_move, mv);
+            to_move = -to_move;
+        }
+        match self.playout {
+            Playout::Random => 0.5,
+            Playout::EvalGuided => {
+                // evaluate_board is positive when White (-1) is better
+                let eval = -player as f64 * evaluate_board(board, to_move, &self.eval) as f64;
+                1.0 / (1.0 + (-eval / EVAL_SCALE).exp())
+            },
+        }
+    }
+
+    fn greedy_move(&mut self, board: &Board, player: i8, moves: &[Move]) -> Move {
+        if self.rng.gen_bool(GREEDY_EPSILON) {
+            return moves[self.rng.gen_range(0..moves.len())];
+        }
+        let mut best_move = moves[0];
+        let mut best_eval = i32::MIN;
+        for &mv in moves {
+            let mut after = *board;
+            apply_move(&mut after, player, mv);
+            let eval = -player as i32 * evaluate_board(&after, -player, &self.eval);
+            if eval > best_eval {
+                best_eval = eval;
+                best_move = mv;
+            }
+        }
+        best_move
+    }
+}
+
+impl Searcher for Mcts {
+    fn search(&mut self, board: &Board, player: i8, limits: &SearchLimits) -> Option<SearchResult> {
+        let start_time = Instant::now();
+        let clock = limits.clock();
+        let max_iterations = limits.nodes.unwrap_or(u64::MAX).min(self.iterations as u64);
+        if limits.deterministic {
+            self.rng = StdRng::seed_from_u64(0);
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        self.info.clear();
+        let mut nodes = vec![Node::new(None, None, board, player)];
+        nodes[0].untried.retain(|mv| self.root_filter.allows(mv));
+        if nodes[0].untried.is_empty() {
+            return None;
+        }
+
+        let in_time = || match clock {
+            Some(clock) => start_time.elapsed() < clock.soft,
+            None => true,
+        };
+        let mut iterations = 0;
+        let mut seldepth = 0;
+        let mut last_report = start_time;
+        while iterations < max_iterations && in_time() && !self.stop.load(Ordering::Relaxed) {
+            iterations += 1;
+            let mut scratch = *board;
+            let mut node = 0;
+
+            // Selection
+            let mut ply = 0;
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                let child = self.select_child(&nodes, node);
+                apply_move(&mut scratch, nodes[node].player, nodes[child].mv.unwrap());
+                node = child;
+                ply += 1;
+            }
+
+            // Expansion
+            if !nodes[node].untried.is_empty() {
+                let pick = self.rng.gen_range(0..nodes[node].untried.len());
+                let mv = nodes[node].untried.swap_remove(pick);
+                let mover = nodes[node].player;
+                apply_move(&mut scratch, mover, mv);
+                let child = nodes.len();
+                nodes.push(Node::new(Some(mv), Some(node), &scratch, -mover));
+                nodes[node].children.push(child);
+                node = child;
+                ply += 1;
+            }
+            seldepth = seldepth.max(ply);
+
+            // Simulation
+            let leaf_player = nodes[node].player;
+            let result = self.simulate(&mut scratch, leaf_player);
+
+            // Backpropagation
+            let mut current = Some(node);
+            while let Some(n) = current {
+                nodes[n].visits += 1;
+                // The reward belongs to the player who moved into this node
+                nodes[n].reward += if nodes[n].player == leaf_player { 1.0 - result } else { result };
+                current = nodes[n].parent;
+            }
+
+            // Progress report: the current main line
+            if iterations & (ITERATIONS_PER_INFO_CHECK - 1) == 0 && last_report.elapsed() >= INFO_INTERVAL {
+                if let Some(&best_child) = nodes[0].children.iter().max_by_key(|&&c| nodes[c].visits) {
+                    let line = principal_line(&nodes, best_child, player);
+                    self.info.send(SearchInfo {
+                        depth: line.depth,
+                        multipv: 1,
+                        score: line.score,
+                        pv: line.pv,
+                        nodes: iterations,
+                        time: start_time.elapsed(),
+                    });
+                }
+                last_report = Instant::now();
+            }
+        }
+        if self.verbose {
+            println!("MCTS: {} iterations, {} nodes", iterations, nodes.len());
+        }
+        let stats = SearchStats { seldepth, nodes: iterations, time: start_time.elapsed(), ..SearchStats::default() };
+
+        if nodes[0].children.is_empty() {
+            // Out of time before the first expansion
+            return Some(SearchResult::single(0, vec![nodes[0].untried[0]], 0).with_stats(stats, Vec::new()));
+        }
+
+        // The most visited root children, each followed down its most
+        // visited descendants for the principal variation
+        let mut root_children = nodes[0].children.clone();
+        root_children.sort_by_key(|&c| std::cmp::Reverse(nodes[c].visits));
+        let lines = root_children
+            .into_iter()
+            .take(self.multi_pv.max(1))
+            .map(|root_child| principal_line(&nodes, root_child, player))
+            .collect();
+        let result = SearchResult::from_lines(lines)?;
+        let depth = result.lines[0].depth;
+        Some(result.with_stats(SearchStats { depth, ..stats }, Vec::new()))
+    }
+}
+
+// The line through `root_child` and its most visited descendants, scored by
+// the child's win rate from White's point of view; `player` moves at the root
+fn principal_line(nodes: &[Node], root_child: usize, player: i8) -> PvLine {
+    let mut pv = vec![nodes[root_child].mv.unwrap()];
+    let mut node = root_child;
+    while let Some(&child) = nodes[node].children.iter().max_by_key(|&&c| nodes[c].visits) {
+        pv.push(nodes[child].mv.unwrap());
+        node = child;
+    }
+    let win_rate = nodes[root_child].reward / nodes[root_child].visits as f64;
+    let score = ((win_rate - 0.5) * 2.0 * SCORE_SCALE) as i32;
+    PvLine { score: -player as i32 * score, depth: pv.len() as i32, pv }
+}
@@ -0,0 +1,301 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{apply_move, get_valid_moves, is_winner, Board, Move};
+
+const INF: u32 = u32::MAX;
+const EXPANSIONS_PER_STOP_CHECK: usize = 1024; // The stop flag is read only every this many expansions
+
+/// Game-theoretic value of a position for the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Proof {
+    Win,
+    Loss,
+    Draw,
+    Unknown,
+}
+
+impl Proof {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Proof::Win => "win",
+            Proof::Loss => "loss",
+            Proof::Draw => "draw",
+            Proof::Unknown => "unknown",
+        }
+    }
+}
+
+/// Result of `solve`: the proven value, the move that achieves it and the
+/// length in plies of the proof line that was found (for wins and losses).
+#[derive(Debug, Clone)]
+pub(crate) struct SolveResult {
+    pub proof: Proof,
+    pub best_move: Option<Move>,
+    pub distance: Option<u32>,
+    pub nodes: usize,
+}
+
+struct PnNode {
+    mv: [u8; 4],
+    parent: u32,
+    first_child: u32,
+    num_children: u16,
+    pn: u32,
+    dn: u32,
+    expanded: bool,
+}
+
+impl PnNode {
+    fn new(mv: Move, parent: usize) -> Self {
+        PnNode {
+            mv: [mv.0 as u8, mv.1 as u8, mv.2 as u8, mv.3 as u8],
+            parent: parent as u32,
+            first_child: 0,
+            num_children: 0,
+            pn: 1,
+            dn: 1,
+            expanded: false,
+        }
+    }
+
+    fn mv(&self) -> Move {
+        (self.mv[0] as usize, self.mv[1] as usize, self.mv[2] as usize, self.mv[3] as usize)
+    }
+
+    fn children(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + self.num_children as usize
+    }
+
+    // Marks the node as decided: `attacker_wins` proves it, otherwise it is disproved
+    fn set_value(&mut self, attacker_wins: bool) {
+        if attacker_wins {
+            self.pn = 0;
+            self.dn = INF;
+        } else {
+            self.pn = INF;
+            self.dn = 0;
+        }
+    }
+}
+
+/// Best-first proof-number search proving whether `attacker` can force a
+/// win. The tree is explicit, so repetitions are detected exactly along each
+/// path: repeating a position counts as a draw, i.e. not a win for the
+/// attacker. Memory is bounded by a node limit.
+struct ProofSearch {
+    nodes: Vec<PnNode>,
+    root_board: Board,
+    root_player: i8,
+    attacker: i8,
+    max_nodes: usize,
+}
+
+impl ProofSearch {
+    fn new(board: &Board, player: i8, attacker: i8, max_nodes: usize) -> Self {
+        let mut root = PnNode::new((0, 0, 0, 0), 0);
+        if let Some(loser) = lost_side(board, player) {
+            root.set_value(loser != attacker);
+        }
+        ProofSearch {
+            nodes: vec![root],
+            root_board: *board,
+            root_player: player,
+            attacker,
+            max_nodes,
+        }
+    }
+
+    fn is_or_node(&self, player: i8) -> bool {
+        player == self.attacker
+    }
+
+    /// Runs until the root is decided, the deadline passes, `stop` is set or
+    /// the node limit is reached.
+    fn run(&mut self, deadline: Option<Instant>, stop: &AtomicBool) {
+        let mut expansions = 0;
+        while self.nodes[0].pn != 0 && self.nodes[0].dn != 0 {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return;
+            }
+            expansions += 1;
+            if expansions % EXPANSIONS_PER_STOP_CHECK == 0 && stop.load(Ordering::Relaxed) {
+                return;
+            }
+
+            // Walk down to the most-proving node
+            let mut board = self.root_board;
+            let mut player = self.root_player;
+            let mut path = vec![board];
+            let mut node = 0;
+            while self.nodes[node].expanded {
+                let or_node = self.is_or_node(player);
+                let children = self.nodes[node].children();
+                node = children
+                    .min_by_key(|&c| if or_node { self.nodes[c].pn } else { self.nodes[c].dn })
+                    .expect("expanded node without children");
+                apply_move(&mut board, player, self.nodes[node].mv());
+                player = -player;
+                path.push(board);
+            }
+
+            if !self.expand(node, &board, player, &path) {
+                return;
+            }
+            self.update_ancestors(node, player);
+        }
+    }
+
+    // Generates and evaluates the children of `node`. Returns false when the
+    // node limit does not allow it.
+    fn expand(&mut self, node: usize, board: &Board, player: i8, path: &[Board]) -> bool {
+        let moves = get_valid_moves(board, player);
+        if self.nodes.len() + moves.len() > self.max_nodes {
+            return false;
+        }
+
+        let first_child = self.nodes.len();
+        for mv in moves {
+            let mut child_board = *board;
+            apply_move(&mut child_board, player, mv);
+            let mut child = PnNode::new(mv, node);
+            if let Some(loser) = lost_side(&child_board, -player) {
+                child.set_value(loser != self.attacker);
+            } else if path.contains(&child_board) {
+                // Repetition: a draw, which is never a win for the attacker
+                child.set_value(false);
+            }
+            self.nodes.push(child);
+        }
+
+        let num_children = self.nodes.len() - first_child;
+        let parent = &mut self.nodes[node];
+        parent.first_child = first_child as u32;
+        parent.num_children = num_children as u16;
+        parent.expanded = true;
+        true
+    }
+
+    fn update_ancestors(&mut self, mut node: usize, mut player: i8) {
+        loop {
+            let or_node = self.is_or_node(player);
+            let children = self.nodes[node].children();
+            let (pn, dn) = if or_node {
+                (
+                    children.clone().map(|c| self.nodes[c].pn).min().unwrap_or(INF),
+                    children.fold(0u32, |acc, c| acc.saturating_add(self.nodes[c].dn)),
+                )
+            } else {
+                (
+                    children.clone().fold(0u32, |acc, c| acc.saturating_add(self.nodes[c].pn)),
+                    children.map(|c| self.nodes[c].dn).min().unwrap_or(INF),
+                )
+            };
+            self.nodes[node].pn = pn;
+            self.nodes[node].dn = dn;
+            if node == 0 {
+                break;
+            }
+            node = self.nodes[node].parent as usize;
+            player = -player;
+        }
+    }
+
+    // Length of the proof tree below a proven node: the attacker takes the
+    // quickest proven child, the defender the slowest.
+    fn proof_distance(&self, node: usize, player: i8) -> u32 {
+        let children = self.nodes[node].children();
+        if children.is_empty() {
+            return 0;
+        }
+        let distances = children
+            .filter(|&c| self.nodes[c].pn == 0)
+            .map(|c| 1 + self.proof_distance(c, -player));
+        if self.is_or_node(player) {
+            distances.min().unwrap_or(0)
+        } else {
+            distances.max().unwrap_or(0)
+        }
+    }
+}
+
+// If the side to move has lost (the opponent reached its last row, or there
+// is no legal move), returns that side.
+fn lost_side(board: &Board, player: i8) -> Option<i8> {
+    if is_winner(board, -player) || get_valid_moves(board, player).is_empty() {
+        Some(player)
+    } else {
+        None
+    }
+}
+
+/// Proves the position a win, loss or draw for `player`. The time and
+/// node limits are shared between the win proof and the loss proof; without
+/// a time limit only the node limit applies. Setting `stop` ends the proofs
+/// early, as with the limits.
+pub(crate) fn solve(board: &Board, player: i8, max_time: Option<Duration>, max_nodes: usize, stop: &AtomicBool) -> SolveResult {
+    let deadline = max_time.map(|max_time| Instant::now() + max_time);
+
+    // Can the side to move force a win?
+    let mut win_search = ProofSearch::new(board, player, player, max_nodes);
+    win_search.run(deadline, stop);
+    let mut nodes = win_search.nodes.len();
+    if win_search.nodes[0].pn == 0 {
+        // Take the quickest of the proven moves
+        let best = win_search.nodes[0]
+            .children()
+            .filter(|&c| win_search.nodes[c].pn == 0)
+            .map(|c| (c, 1 + win_search.proof_distance(c, -player)))
+            .min_by_key(|&(_, distance)| distance);
+        return SolveResult {
+            proof: Proof::Win,
+            best_move: best.map(|(c, _)| win_search.nodes[c].mv()),
+            distance: Some(best.map_or(0, |(_, distance)| distance)),
+            nodes,
+        };
+    }
+    let win_disproved = win_search.nodes[0].dn == 0;
+    drop(win_search);
+
+    // Can the opponent force a win?
+    let mut loss_search = ProofSearch::new(board, player, -player, max_nodes);
+    loss_search.run(deadline, stop);
+    nodes += loss_search.nodes.len();
+    let root = &loss_search.nodes[0];
+    if root.pn == 0 {
+        // Every move loses; resist as long as possible
+        let best = root
+            .children()
+            .map(|c| (c, 1 + loss_search.proof_distance(c, -player)))
+            .max_by_key(|&(_, distance)| distance);
+        return SolveResult {
+            proof: Proof::Loss,
+            best_move: best.map(|(c, _)| loss_search.nodes[c].mv()),
+            distance: Some(best.map_or(0, |(_, distance)| distance)),
+            nodes,
+        };
+    }
+    if root.dn == 0 && win_disproved {
+        // Neither side can force a win; a disproved child is a move that holds the draw
+        let best = root.children().find(|&c| loss_search.nodes[c].dn == 0);
+        return SolveResult {
+            proof: Proof::Draw,
+            best_move: best.map(|c| loss_search.nodes[c].mv()),
+            distance: None,
+            nodes,
+        };
+    }
+
+    SolveResult {
+        proof: Proof::Unknown,
+        best_move: None,
+        distance: None,
+        nodes,
+    }
+}
+
+/// Node limit that fits in `memory_mb` megabytes.
+pub(crate) fn nodes_for_memory(memory_mb: usize) -> usize {
+    memory_mb * 1024 * 1024 / std::mem::size_of::<PnNode>()
+}
@@ -0,0 +1,192 @@
+use crate::{Board, ROWS};
+
+/// Proven outcome of a stone race: `winner` reaches its last row at the
+/// latest on ply `plies`, counted from the current position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Race {
+    pub winner: i8,
+    pub plies: u32,
+}
+
+/// Solves the position as a race when one side provably gets a runner home
+/// first, with `player_to_move` to move; None when the position is not a
+/// decided race. A runner is a stone no enemy stone can ever block or
+/// capture: no enemy stone can get next to its path before the runner has
+/// passed its row. Its side is then counted as moving it every turn, except
+/// for one forced capture per enemy stone its other stones could ever meet,
+/// and wins if it arrives before the earliest ply the other side could
+/// arrive even with a capture on every move it has stones to jump over.
+pub(crate) fn solve(board: &Board, player_to_move: i8) -> Option<Race> {
+    for player in [player_to_move, -player_to_move] {
+        let Some(arrival) = runner_arrival(board, player, player_to_move) else {
+            continue;
+        };
+        if arrival < earliest_arrival(board, -player, player_to_move) {
+            return Some(Race { winner: player, plies: arrival });
+        }
+    }
+    None
+}
+
+// Rows `player`'s stone on `row` still has to go
+fn goal_distance(row: usize, player: i8) -> u32 {
+    if player == 1 {
+        (ROWS - 1 - row) as u32
+    } else {
+        row as u32
+    }
+}
+
+// Ply of `player`'s `moves`-th move from now (0 for no move at all)
+fn ply_of_move(moves: u32, player: i8, player_to_move: i8) -> u32 {
+    if moves == 0 {
+        0
+    } else if player == player_to_move {
+        2 * moves - 1
+    } else {
+        2 * moves
+    }
+}
+
+fn stones(board: &Board, player: i8) -> Vec<(usize, usize)> {
+    let mut stones = Vec::new();
+    for (i, row) in board.iter().enumerate() {
+        for (j, &piece) in row.iter().enumerate() {
+            if piece == player {
+                stones.push((i, j));
+            }
+        }
+    }
+    stones
+}
+
+// The ply on which `player`'s fastest runner is home at the latest, if it
+// has one
+fn runner_arrival(board: &Board, player: i8, player_to_move: i8) -> Option<u32> {
+    let own = stones(board, player);
+    let enemy = stones(board, -player);
+    let mut best: Option<u32> = None;
+    for &(row, col) in &own {
+        let distance = goal_distance(row, player);
+        if best.is_some_and(|best| ply_of_move(distance, player, player_to_move) >= best) {
+            continue;
+        }
+        let others = || own.iter().filter(|&&stone| stone != (row, col));
+        // Own stones level with or ahead of the runner give enemy stones
+        // something to jump over, two columns in a single move
+        let enemy_reach = if others().any(|&(i, _)| goal_distance(i, player) <= distance) { 2 } else { 1 };
+        if !is_runner(board, player, player_to_move, (row, col), enemy_reach) {
+            continue;
+        }
+        // Enemy stones that can still come in front of one of the other
+        // stones, each of which may force a capture that costs a move
+        let can_capture = |&(i, _): &(usize, usize), &(k, _): &(usize, usize)| goal_distance(k, player) < goal_distance(i, player);
+        let forced = enemy.iter().filter(|e| others().any(|x| can_capture(x, e))).count() as u32;
+        // Capturing stones keep their column parity, so they can never land
+        // in the runner's path if it differs
+        if others().any(|x| (x.1 + col) % 2 == 0 && enemy.iter().any(|e| can_capture(x, e))) {
+            continue;
+        }
+        let arrival = ply_of_move(distance + forced, player, player_to_move);
+        best = Some(best.map_or(arrival, |best| best.min(arrival)));
+    }
+    best
+}
+
+// Whether `player`'s stone at `runner` has a free path home: no own stone in
+// front of it and no enemy stone, covering `enemy_reach` columns per move,
+// able to reach a column next to the path before the runner passes its row
+fn is_runner(board: &Board, player: i8, player_to_move: i8, runner: (usize, usize), enemy_reach: u32) -> bool {
+    let (row, col) = runner;
+    let distance = goal_distance(row, player);
+    for (i, cells) in board.iter().enumerate() {
+        let rows_to_pass = match distance.checked_sub(goal_distance(i, player)) {
+            Some(rows) if rows > 0 => rows,
+            _ => continue, // Level with or behind the runner
+        };
+        for (j, &piece) in cells.iter().enumerate() {
+            if piece == player && j == col {
+                return false;
+            }
+            if piece == -player {
+                let gap = (j as i32 - col as i32).unsigned_abs().saturating_sub(1);
+                let moves = gap.div_ceil(enemy_reach);
+                if ply_of_move(moves, -player, player_to_move) < ply_of_move(rows_to_pass, player, player_to_move) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+// A lower bound on the ply on which `player` can get a stone home: each
+// stone needs a move per row, or one per two rows while there are enemy
+// stones in front of it to capture
+fn earliest_arrival(board: &Board, player: i8, player_to_move: i8) -> u32 {
+    let enemy = stones(board, -player);
+    stones(board, player)
+        .into_iter()
+        .map(|(row, _)| {
+            let distance = goal_distance(row, player);
+            let in_front = enemy.iter().filter(|&&(i, _)| goal_distance(i, player) < distance).count() as u32;
+            ply_of_move(distance - in_front.min(distance / 2), player, player_to_move)
+        })
+        .min()
+        .unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tablebase::{Tablebase, TbValue};
+    use crate::{is_game_over, is_winner, COLS};
+
+    // Every race the solver claims must be a tablebase win for its winner,
+    // reached within the claimed number of plies
+    fn check_against(tablebase: &Tablebase, board: &Board, player: i8) -> bool {
+        let Some(race) = solve(board, player) else {
+            return false;
+        };
+        match tablebase.probe(board, player) {
+            Some(TbValue::Win(d)) => assert!(race.winner == player && d as u32 <= race.plies, "{:?} {}", board, player),
+            Some(TbValue::Loss(d)) => assert!(race.winner == -player && d as u32 <= race.plies, "{:?} {}", board, player),
+            other => panic!("race {:?} but tablebase {:?} for {:?} {}", race, other, board, player),
+        }
+        true
+    }
+
+    #[test]
+    fn one_stone_races_match_the_tablebase() {
+        let tablebase = Tablebase::generate(1);
+        let mut claims = 0;
+        for white in 0..ROWS * COLS {
+            for black in 0..ROWS * COLS {
+                if white == black {
+                    continue;
+                }
+                let mut board = [[0; COLS]; ROWS];
+                board[white / COLS][white % COLS] = -1;
+                board[black / COLS][black % COLS] = 1;
+                if is_winner(&board, -1) || is_winner(&board, 1) {
+                    continue;
+                }
+                for player in [-1, 1] {
+                    if !is_game_over(&board, player) && check_against(&tablebase, &board, player) {
+                        claims += 1;
+                    }
+                }
+            }
+        }
+        assert!(claims > 1000, "only {} races proven", claims);
+    }
+
+    #[test]
+    fn blocked_runner_is_no_race() {
+        // White's stone stands right in front of Black's
+        let mut board = [[0; COLS]; ROWS];
+        board[4][4] = 1;
+        board[5][4] = -1;
+        assert_eq!(solve(&board, 1), None);
+    }
+}
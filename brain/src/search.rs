@@ -0,0 +1,1188 @@
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::contempt::Contempt;
+use crate::eval::EvalParams;
+use crate::info::{InfoQueue, SearchInfo, INFO_INTERVAL};
+use crate::pns::{self, Proof};
+use crate::race;
+use crate::searcher::{PvLine, RootFilter, SearchLimits, SearchResult, SearchStats, Searcher};
+use crate::tablebase::{Tablebase, TbValue};
+use crate::timeman::{Deadline, TimeLimits};
+use crate::tt::{SharedTT, TTEntry, TTFlag};
+use crate::{apply_move, count_stones, evaluate_stones, get_valid_moves, is_game_over, Board, Move, COLS, FORCED_SCORE, MAX_PLY, MAX_SCORE, MIN_SCORE, ROWS};
+
+pub(crate) const DEFAULT_HASH_MB: usize = 128; // Transposition table size, shared by all search threads
+const SOLVER_MAX_STONES: usize = 6; // Positions with this many stones or fewer go to the proof-number solver first
+const SOLVER_MEMORY_MB: usize = 64;
+const SOLVER_TIME_FRACTION: u32 = 10; // Share of the move time the solver may use
+const NODES_PER_TIME_CHECK: u64 = 1024; // The clock is read and the shared counters are updated only every this many nodes (a power of two)
+
+// Lines of one completed iteration, best first
+pub(crate) type DepthResult = Vec<PvLine>;
+
+/// Iterative-deepening negamax with a transposition table, optionally
+/// parallelised with Lazy SMP.
+pub(crate) struct AlphaBeta {
+    tt: Arc<SharedTT>,
+    zobrist_table: Vec<Vec<[u64; 2]>>, // [ROWS][COLS][2]
+    game_history: Vec<u64>, // Keys of the positions played before the one searched, oldest first
+    ai_player: i8,
+    pub threads: usize,
+    pub multi_pv: usize,
+    pub root_filter: RootFilter,
+    pub solver_max_stones: usize,
+    pub solver_memory_mb: usize,
+    pub tablebase: Option<Arc<Tablebase>>,
+    pub contempt: Contempt,
+    pub eval: EvalParams,
+    pub verbose: bool, // Print progress to stdout
+    pub info: Arc<InfoQueue>, // Progress reports for Python to poll
+    pub stop: Arc<AtomicBool>, // Set from outside to end the current search early
+    ponder: Option<Ponder>,
+}
+
+/// Search running in the background on the position after the expected
+/// reply, with no time limit until the opponent's real move arrives.
+struct Ponder {
+    board: Board,
+    player: i8,
+    game_history: Vec<u64>,
+    multi_pv: usize,
+    stop: Arc<AtomicBool>,
+    deadline: Arc<Deadline>,
+    handle: JoinHandle<JobOutput>,
+}
+
+impl AlphaBeta {
+    pub fn new(ai_player: i8, threads: usize) -> Self {
+        AlphaBeta {
+            tt: Arc::new(SharedTT::new(DEFAULT_HASH_MB)),
+            zobrist_table: zobrist_table(),
+            game_history: Vec::new(),
+            ai_player,
+            threads: threads.max(1),
+            multi_pv: 1,
+            root_filter: RootFilter::default(),
+            solver_max_stones: SOLVER_MAX_STONES,
+            solver_memory_mb: SOLVER_MEMORY_MB,
+            tablebase: None,
+            contempt: Contempt::new(),
+            eval: EvalParams::default(),
+            verbose: false,
+            info: Arc::new(InfoQueue::default()),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: None,
+        }
+    }
+
+    pub fn tt_size(&self) -> usize {
+        self.tt.len()
+    }
+
+    /// Replaces the transposition table by an empty one of at most
+    /// `megabytes` MB, cancelling pondering first.
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.stop_ponder();
+        self.tt = Arc::new(SharedTT::new(megabytes));
+    }
+
+    pub fn hash_size(&self) -> usize {
+        self.tt.megabytes()
+    }
+
+    /// Empties the transposition table, e.g. for a new game, cancelling
+    /// pondering first.
+    pub fn clear_hash(&mut self) {
+        self.stop_ponder();
+        self.tt.clear();
+    }
+
+    /// Saves the transposition table entries searched at least `min_depth`
+    /// deep to `path`, returning how many.
+    pub fn save_hash(&self, path: &Path, min_depth: i32) -> io::Result<usize> {
+        self.tt.save(path, min_depth)
+    }
+
+    /// Adds the entries of a saved table to the transposition table,
+    /// returning how many.
+    pub fn load_hash(&self, path: &Path) -> io::Result<usize> {
+        self.tt.load(path)
+    }
+
+    /// Sets the positions played so far in the game, oldest first and
+    /// without the one to be searched, so repetitions in the search count
+    /// the real earlier occurrences.
+    pub fn set_game_history(&mut self, positions: &[Board]) {
+        self.game_history = positions.iter().map(|board| zobrist_key(&self.zobrist_table, board)).collect();
+    }
+
+    /// Number of positions in the game history, i.e. plies played so far.
+    pub fn game_ply(&self) -> usize {
+        self.game_history.len()
+    }
+
+    /// Forgets the game history and the contempt trend and cancels pondering.
+    pub fn new_game(&mut self) {
+        self.stop_ponder();
+        self.game_history.clear();
+        self.contempt.clear_trend();
+    }
+
+    /// Starts searching `board`, the position expected after the opponent's
+    /// reply, in the background. `played` are the positions between the
+    /// current game history and `board` (the position just searched and the
+    /// one after the engine's move). Whatever pondering finds goes into the
+    /// shared transposition table, so it helps the next search even on a miss.
+    pub fn start_ponder(&mut self, board: &Board, player: i8, played: &[Board], max_depth: i32, multi_pv: usize) {
+        self.stop_ponder();
+        let stop = Arc::new(AtomicBool::new(false));
+        let deadline = Arc::new(Deadline::unlimited());
+        let mut game_history = self.game_history.clone();
+        game_history.extend(played.iter().map(|board| zobrist_key(&self.zobrist_table, board)));
+        self.tt.new_search();
+        let mut job = self.job(game_history.clone(), stop.clone(), deadline.clone());
+        job.multi_pv = multi_pv;
+        job.root_filter = RootFilter::default();
+        job.info = None; // Reports are about the position Python asked for
+        let mut ponder_board = *board;
+        let handle = std::thread::spawn(move || job.run(&mut ponder_board, player, max_depth));
+        self.ponder = Some(Ponder { board: *board, player, game_history, multi_pv, stop, deadline, handle });
+    }
+
+    /// Cancels pondering, if any.
+    pub fn stop_ponder(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            ponder.stop.store(true, Ordering::Relaxed);
+            let _ = ponder.handle.join();
+        }
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.is_some()
+    }
+
+    // The opponent played the expected move: the ponder search becomes the
+    // real one, keeping the depth it already reached, and gets `limits`
+    // from now on and the depth limit of the real search. Iterations the
+    // ponder already completed beyond that depth are not used.
+    fn ponderhit(&mut self, ponder: Ponder, limits: Option<TimeLimits>, max_depth: i32) -> Option<SearchResult> {
+        ponder.deadline.limit_depth(max_depth);
+        if let Some(limits) = limits {
+            ponder.deadline.reset(limits);
+        }
+        // Wait for it, passing on a stop request
+        while !ponder.handle.is_finished() {
+            if self.stop.load(Ordering::Relaxed) {
+                ponder.stop.store(true, Ordering::Relaxed);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let mut output = ponder.handle.join().ok()?;
+        if self.verbose {
+            println!("Ponderhit after {:.1}s", ponder.deadline.elapsed().as_secs_f64());
+        }
+        output.depth_results.retain(|lines| lines.first().is_some_and(|line| line.depth <= max_depth));
+        output.iterations.retain(|iteration| iteration.depth <= max_depth);
+        output.stats.depth = output.iterations.last().map_or(0, |iteration| iteration.depth);
+        Some(SearchResult::from_lines(output.depth_results.last().cloned()?)?.with_stats(output.stats, output.iterations))
+    }
+
+    fn job(&self, hash_history: Vec<u64>, stop: Arc<AtomicBool>, deadline: Arc<Deadline>) -> SearchJob {
+        SearchJob {
+            tt: self.tt.clone(),
+            zobrist_table: self.zobrist_table.clone(),
+            hash_history,
+            ai_player: self.ai_player,
+            threads: self.threads,
+            multi_pv: self.multi_pv,
+            root_filter: self.root_filter.clone(),
+            tablebase: self.tablebase.clone(),
+            contempt: self.contempt.value(true),
+            eval: self.eval,
+            node_budget: None,
+            verbose: self.verbose,
+            info: Some(self.info.clone()),
+            stop,
+            deadline,
+        }
+    }
+}
+
+impl Drop for AlphaBeta {
+    fn drop(&mut self) {
+        self.stop_ponder();
+    }
+}
+
+// Everything a (possibly parallel) iterative-deepening search needs, owned so
+// it can also run on a background thread while pondering
+struct SearchJob {
+    tt: Arc<SharedTT>,
+    zobrist_table: Vec<Vec<[u64; 2]>>,
+    hash_history: Vec<u64>,
+    ai_player: i8,
+    threads: usize,
+    multi_pv: usize,
+    root_filter: RootFilter,
+    tablebase: Option<Arc<Tablebase>>,
+    contempt: i32,
+    eval: EvalParams,
+    node_budget: Option<u64>, // Nodes over all threads
+    verbose: bool,
+    info: Option<Arc<InfoQueue>>,
+    stop: Arc<AtomicBool>,
+    deadline: Arc<Deadline>,
+}
+
+// What a finished search job hands back: the lines and statistics of every
+// completed iteration, and the statistics of the whole search
+struct JobOutput {
+    depth_results: Vec<DepthResult>,
+    iterations: Vec<SearchStats>,
+    stats: SearchStats,
+}
+
+// Counters of one thread, added to the job's shared totals every
+// NODES_PER_TIME_CHECK nodes
+#[derive(Default)]
+struct Counters {
+    nodes: u64,
+    tt_probes: u64,
+    tt_hits: u64,
+    tt_collisions: u64,
+    beta_cutoffs: u64,
+    first_move_cutoffs: u64,
+}
+
+#[derive(Default)]
+struct SharedCounters {
+    nodes: AtomicU64,
+    tt_probes: AtomicU64,
+    tt_hits: AtomicU64,
+    tt_collisions: AtomicU64,
+    beta_cutoffs: AtomicU64,
+    first_move_cutoffs: AtomicU64,
+}
+
+impl SharedCounters {
+    fn add(&self, counters: &Counters) {
+        self.nodes.fetch_add(counters.nodes, Ordering::Relaxed);
+        self.tt_probes.fetch_add(counters.tt_probes, Ordering::Relaxed);
+        self.tt_hits.fetch_add(counters.tt_hits, Ordering::Relaxed);
+        self.tt_collisions.fetch_add(counters.tt_collisions, Ordering::Relaxed);
+        self.beta_cutoffs.fetch_add(counters.beta_cutoffs, Ordering::Relaxed);
+        self.first_move_cutoffs.fetch_add(counters.first_move_cutoffs, Ordering::Relaxed);
+    }
+
+    // The totals so far; depth, seldepth, time and hashfull are left to the
+    // caller
+    fn stats(&self) -> SearchStats {
+        SearchStats {
+            nodes: self.nodes.load(Ordering::Relaxed),
+            tt_probes: self.tt_probes.load(Ordering::Relaxed),
+            tt_hits: self.tt_hits.load(Ordering::Relaxed),
+            tt_collisions: self.tt_collisions.load(Ordering::Relaxed),
+            beta_cutoffs: self.beta_cutoffs.load(Ordering::Relaxed),
+            first_move_cutoffs: self.first_move_cutoffs.load(Ordering::Relaxed),
+            ..SearchStats::default()
+        }
+    }
+}
+
+impl SearchJob {
+    fn run(self, board: &mut Board, player: i8, max_depth: i32) -> JobOutput {
+        let counters = Arc::new(SharedCounters::default());
+        let new_thread = |id: usize, hash_history: Vec<u64>| {
+            let mut thread = SearchThread::new(
+                id,
+                self.tt.clone(),
+                self.zobrist_table.clone(),
+                hash_history,
+                self.ai_player,
+                self.tablebase.clone(),
+                self.stop.clone(),
+                self.deadline.clone(),
+            );
+            thread.root_filter = self.root_filter.clone();
+            thread.contempt = self.contempt;
+            thread.eval = self.eval;
+            thread.node_budget = self.node_budget;
+            thread.shared_counters = counters.clone();
+            thread
+        };
+        let mut main_thread = new_thread(0, self.hash_history.clone());
+        main_thread.multi_pv = self.multi_pv;
+        main_thread.verbose = self.verbose;
+        main_thread.info = self.info.clone();
+
+        // Push the current hash key onto the stack
+        let root_key = main_thread.compute_hash_key(board);
+        main_thread.hash_history.push(root_key);
+
+        // Lazy SMP: helpers run the same iterative deepening on their own
+        // board copies and only share results through the transposition
+        // table. With a single thread no helper is spawned and the search
+        // is exactly the sequential one.
+        let depth_results = std::thread::scope(|s| {
+            for id in 1..self.threads {
+                let mut helper = new_thread(id, main_thread.hash_history.clone());
+                let mut helper_board = *board;
+                s.spawn(move || helper.iterative_deepening(&mut helper_board, player, max_depth));
+            }
+
+            let depth_results = main_thread.iterative_deepening(board, player, max_depth);
+            // Tell the helpers to stop once the main thread is done
+            self.stop.store(true, Ordering::Relaxed);
+            depth_results
+        });
+
+        let mut stats = counters.stats();
+        stats.depth = main_thread.iterations.last().map_or(0, |iteration| iteration.depth);
+        stats.seldepth = main_thread.iterations.iter().map(|iteration| iteration.seldepth).max().unwrap_or(0);
+        stats.time = self.deadline.elapsed();
+        stats.hashfull = self.tt.hashfull();
+        JobOutput {
+            depth_results,
+            iterations: main_thread.iterations,
+            stats,
+        }
+    }
+}
+
+impl Searcher for AlphaBeta {
+    fn search(&mut self, board: &Board, player: i8, limits: &SearchLimits) -> Option<SearchResult> {
+        let result = self.search_position(board, player, limits);
+        // Deterministic searches leave no trace in the contempt trend
+        if let (Some(result), false) = (&result, limits.deterministic) {
+            self.contempt.record(-self.ai_player as i32 * result.score);
+        }
+        result
+    }
+}
+
+impl AlphaBeta {
+    fn search_position(&mut self, board: &Board, player: i8, limits: &SearchLimits) -> Option<SearchResult> {
+        let mut board_state = *board;
+        let clock = limits.clock();
+        let deadline = Arc::new(clock.map_or_else(Deadline::unlimited, Deadline::new));
+        self.stop.store(false, Ordering::Relaxed);
+        self.info.clear();
+
+        // A deterministic search must not see anything earlier searches left
+        if limits.deterministic {
+            self.stop_ponder();
+            self.tt.clear();
+        }
+
+        // Pondering pays off when the opponent played the expected move;
+        // otherwise it is cancelled and only its TT entries remain. It runs
+        // without a node budget, so a budgeted search starts afresh.
+        if let Some(ponder) = self.ponder.take() {
+            let same_search = ponder.multi_pv == self.multi_pv && self.root_filter.is_empty() && limits.nodes.is_none();
+            let same_game = ponder.game_history == self.game_history;
+            if same_search && same_game && ponder.board == *board && ponder.player == player {
+                if let Some(result) = self.ponderhit(ponder, clock, limits.max_depth) {
+                    return Some(result);
+                }
+            } else {
+                self.ponder = Some(ponder);
+                self.stop_ponder();
+            }
+        }
+
+        // Positions covered by the tablebase are played straight from it. In
+        // multi-PV mode or with restricted root moves the search ranks the
+        // moves instead, still using the exact tablebase values below the root.
+        let full_root = self.multi_pv == 1 && self.root_filter.is_empty();
+        if let (Some(tablebase), true) = (&self.tablebase, full_root) {
+            if let Some((value, best_move)) = tablebase.best_move(board, player) {
+                let draw = draw_score(self.contempt.value(!limits.deterministic), player, self.ai_player);
+                let score = tablebase_score(value, 0, draw);
+                if self.verbose {
+                    println!("Tablebase: {:?}", value);
+                }
+                return Some(SearchResult::single(-player as i32 * score, vec![best_move], 0));
+            }
+        }
+
+        // Endgame step: small positions are often solved outright by the
+        // proof-number solver. A proven win is played directly; otherwise the
+        // normal search picks the move.
+        if full_root && count_stones(board) <= self.solver_max_stones {
+            let solved = pns::solve(
+                board,
+                player,
+                clock.map(|clock| clock.soft / SOLVER_TIME_FRACTION),
+                pns::nodes_for_memory(self.solver_memory_mb),
+                &self.stop,
+            );
+            if self.verbose {
+                println!("Solver: {} in {} plies ({} nodes)", solved.proof.name(), solved.distance.unwrap_or(0), solved.nodes);
+            }
+            if let (Proof::Win, Some(best_move)) = (solved.proof, solved.best_move) {
+                // The proof line found need not be the shortest one
+                let distance = solved.distance.unwrap_or(0).min(MAX_PLY as u32) as i32;
+                let stats = SearchStats { nodes: solved.nodes as u64, time: deadline.elapsed(), ..SearchStats::default() };
+                let score = bounded_win_score(distance);
+                return Some(SearchResult::single(-player as i32 * score, vec![best_move], distance).with_stats(stats, Vec::new()));
+            }
+        }
+
+        // Entries of earlier searches are kept but now give way to new ones
+        self.tt.new_search();
+        let mut job = self.job(self.game_history.clone(), self.stop.clone(), deadline);
+        job.node_budget = limits.nodes;
+        if limits.deterministic {
+            job.threads = 1;
+            job.contempt = self.contempt.value(false);
+        }
+        let output = job.run(&mut board_state, player, limits.max_depth);
+
+        // Win and loss scores carry their distance, so the deepest completed
+        // iteration already prefers the quickest win and the slowest loss
+        let lines = match output.depth_results.last() {
+            Some(lines) => lines.clone(),
+            None => {
+                // Stopped before the first iteration finished: any allowed move
+                let mv = get_valid_moves(board, player).into_iter().find(|mv| self.root_filter.allows(mv))?;
+                return Some(SearchResult::single(0, vec![mv], 0).with_stats(output.stats, output.iterations));
+            },
+        };
+
+        Some(SearchResult::from_lines(lines)?.with_stats(output.stats, output.iterations))
+    }
+}
+
+/// Random keys per square and colour, the same on every run and every
+/// build (saved books and transposition tables rely on that), so they come
+/// from splitmix64 here rather than from a library generator.
+pub(crate) fn zobrist_table() -> Vec<Vec<[u64; 2]>> {
+    let mut state = 0u64;
+    let mut zobrist_table: Vec<Vec<[u64; 2]>> = vec![vec![[0u64; 2]; COLS]; ROWS]; // [ROWS][COLS][2]
+    for row in zobrist_table.iter_mut() {
+        for square in row.iter_mut() {
+            for key in square.iter_mut() {
+                *key = splitmix64(&mut state);
+            }
+        }
+    }
+    zobrist_table
+}
+
+// Steele, Lea and Flood's SplitMix64 generator
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub(crate) fn zobrist_key(zobrist_table: &[Vec<[u64; 2]>], board: &Board) -> u64 {
+    let mut hash_key = 0u64;
+    for (i, row) in board.iter().enumerate() {
+        for (j, &piece) in row.iter().enumerate() {
+            if piece != 0 {
+                let piece_index = if piece == -1 { 0 } else { 1 };
+                hash_key ^= zobrist_table[i][j][piece_index];
+            }
+        }
+    }
+    hash_key
+}
+
+// Score for the side to move of a position won `ply` plies from the root.
+// Nearer wins and further losses score better.
+fn win_score(ply: i32) -> i32 {
+    MAX_SCORE - ply
+}
+
+fn loss_score(ply: i32) -> i32 {
+    -MAX_SCORE + ply
+}
+
+// Score of a position won at the latest `ply` plies from the root, for wins
+// whose exact distance is unknown. Any exact distance ranks above it, so the
+// search never takes a bound for the fastest win.
+fn bounded_win_score(ply: i32) -> i32 {
+    win_score(ply) - MAX_PLY
+}
+
+fn bounded_loss_score(ply: i32) -> i32 {
+    loss_score(ply) + MAX_PLY
+}
+
+fn is_decisive(score: i32) -> bool {
+    score.abs() >= FORCED_SCORE
+}
+
+// The transposition table stores win and loss distances from the entry's own
+// position, since the same position can be reached at different plies
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if !is_decisive(score) {
+        score
+    } else if score > 0 {
+        score + ply
+    } else {
+        score - ply
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if !is_decisive(score) {
+        score
+    } else if score > 0 {
+        score - ply
+    } else {
+        score + ply
+    }
+}
+
+// Negamax score of a draw for `player` to move: the engine's contempt counts
+// against it and for its opponent
+fn draw_score(contempt: i32, player: i8, ai_player: i8) -> i32 {
+    if player == ai_player {
+        -contempt
+    } else {
+        contempt
+    }
+}
+
+// Negamax score of a tablebase value for the side to move at `ply`, given
+// that side's `draw` score
+fn tablebase_score(value: TbValue, ply: i32, draw: i32) -> i32 {
+    match value {
+        TbValue::Win(d) => win_score(ply + d as i32),
+        TbValue::Loss(d) => loss_score(ply + d as i32),
+        TbValue::Draw => draw,
+    }
+}
+
+/// One alpha-beta worker. The main thread has id 0; Lazy SMP helpers get
+/// higher ids and only contribute through the shared transposition table.
+pub(crate) struct SearchThread {
+    id: usize,
+    tt: Arc<SharedTT>,
+    zobrist_table: Vec<Vec<[u64; 2]>>,
+    pub hash_history: Vec<u64>,
+    ai_player: i8,
+    root_move_scores: HashMap<Move, i32>,
+    multi_pv: usize,
+    root_filter: RootFilter,
+    root_excluded: Vec<Move>, // Root moves already reported in this multi-PV iteration
+    pv_table: Vec<Vec<Move>>, // Triangular PV table: the best line found from each ply
+    tablebase: Option<Arc<Tablebase>>,
+    contempt: i32,
+    eval: EvalParams,
+    stop: Arc<AtomicBool>,
+    deadline: Arc<Deadline>,
+    node_budget: Option<u64>,
+    counters: Counters, // Not yet added to shared_counters
+    shared_counters: Arc<SharedCounters>, // Totals of all threads of the job
+    seldepth: i32, // Of the current iteration
+    pub iterations: Vec<SearchStats>, // Collected by the main thread only
+    verbose: bool,
+    info: Option<Arc<InfoQueue>>, // Main thread only
+    current_depth: i32,
+    best_line: Option<PvLine>, // Of the last completed iteration
+    last_report: Duration,
+    timed_out: bool, // Sticky once the hard limit, the node budget or a stop was seen
+}
+
+impl SearchThread {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: usize,
+        tt: Arc<SharedTT>,
+        zobrist_table: Vec<Vec<[u64; 2]>>,
+        hash_history: Vec<u64>,
+        ai_player: i8,
+        tablebase: Option<Arc<Tablebase>>,
+        stop: Arc<AtomicBool>,
+        deadline: Arc<Deadline>,
+    ) -> Self {
+        SearchThread {
+            id,
+            tt,
+            zobrist_table,
+            hash_history,
+            ai_player,
+            root_move_scores: HashMap::new(),
+            multi_pv: 1,
+            root_filter: RootFilter::default(),
+            root_excluded: Vec::new(),
+            pv_table: Vec::new(),
+            tablebase,
+            contempt: 0,
+            eval: EvalParams::default(),
+            stop,
+            deadline,
+            node_budget: None,
+            counters: Counters::default(),
+            shared_counters: Arc::new(SharedCounters::default()),
+            seldepth: 0,
+            iterations: Vec::new(),
+            verbose: false,
+            info: None,
+            current_depth: 0,
+            best_line: None,
+            last_report: Duration::ZERO,
+            timed_out: false,
+        }
+    }
+
+    /// Runs iterative deepening up to `max_depth` and returns the lines of
+    /// every completed iteration. In multi-PV mode each iteration searches
+    /// the root again without the moves already found, so every line gets an
+    /// exact score.
+    pub fn iterative_deepening(&mut self, board: &mut Board, player: i8, max_depth: i32) -> Vec<DepthResult> {
+        let mut depth_results = Vec::new();
+        let root_key = self.compute_hash_key(board);
+
+        // Helpers with odd ids start one ply deeper so the threads spread
+        // over neighbouring depths instead of all racing on the same one.
+        let first_depth = 1 + (self.id % 2) as i32;
+
+        for depth in first_depth..=max_depth {
+
+            if depth > self.deadline.max_depth() {
+                break;
+            }
+            if self.out_of_time() {
+                if self.verbose {
+                    println!("Time limit reached. Breaking out of the search loop.");
+                }
+                break;
+            }
+            self.seldepth = 0;
+            self.current_depth = depth;
+
+            let mut lines = Vec::new();
+            let mut timed_out = false;
+            self.root_excluded.clear();
+            while lines.len() < self.multi_pv {
+                // Copy the current hash key
+                let mut hash_key = root_key;
+
+                // Call the Negamax algorithm with the Transposition Table
+                let result = self.negamax(
+                    board,
+                    depth,
+                    player,
+                    MIN_SCORE,
+                    MAX_SCORE,
+                    &mut hash_key,
+                    0,
+                );
+
+                match result {
+                    Ok(score) => {
+                        let root_move = match self.pv_table[0].first() {
+                            Some(&root_move) => root_move,
+                            None => break, // Every root move is already in a line
+                        };
+                        self.root_excluded.push(root_move);
+                        let pv = self.complete_pv(board, player, self.pv_table[0].clone(), depth);
+                        lines.push(PvLine { score: -player as i32 * score, pv, depth });
+                    },
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    },
+                }
+            }
+
+            if timed_out {
+                // Time limit reached during negamax; break out of the loop
+                if self.verbose {
+                    println!("Time limit reached during negamax. Breaking out of the search loop.");
+                }
+                break;
+            }
+            // Best first; later lines can only score higher through TT noise
+            lines.sort_by_key(|line| player as i32 * line.score);
+            if self.id == 0 {
+                self.record_iteration(depth, &lines);
+            }
+            if self.verbose {
+                for line in &lines {
+                    println!("Depth {}: Best Score = {}, PV = {:?}", depth, line.score, line.pv);
+                }
+            }
+            depth_results.push(lines);
+        }
+
+        self.flush_counters();
+        depth_results
+    }
+
+    // Statistics of the search up to the iteration just completed, also
+    // reported with its lines
+    fn record_iteration(&mut self, depth: i32, lines: &[PvLine]) {
+        self.flush_counters();
+        let mut stats = self.shared_counters.stats();
+        stats.depth = depth;
+        stats.seldepth = self.seldepth;
+        stats.time = self.deadline.elapsed();
+        stats.hashfull = self.tt.hashfull();
+        if self.verbose {
+            println!(
+                "Depth {}: seldepth {}, {} nodes, {} nps, TT hits {}/{} ({} collisions), first-move cutoffs {:.0}%, hashfull {}",
+                depth,
+                stats.seldepth,
+                stats.nodes,
+                stats.nps(),
+                stats.tt_hits,
+                stats.tt_probes,
+                stats.tt_collisions,
+                100.0 * stats.first_move_cutoff_rate(),
+                stats.hashfull,
+            );
+        }
+        if let Some(info) = &self.info {
+            for (i, line) in lines.iter().enumerate() {
+                info.send(SearchInfo {
+                    depth,
+                    multipv: i + 1,
+                    score: line.score,
+                    pv: line.pv.clone(),
+                    nodes: stats.nodes,
+                    time: stats.time,
+                });
+            }
+        }
+        self.last_report = stats.time;
+        self.best_line = lines.first().cloned();
+        self.iterations.push(stats);
+    }
+
+    // Inside an iteration: the depth being searched with the last completed
+    // main line, every INFO_INTERVAL
+    fn report_progress(&mut self) {
+        let (Some(info), Some(line)) = (&self.info, &self.best_line) else {
+            return;
+        };
+        let time = self.deadline.elapsed();
+        if time < self.last_report + INFO_INTERVAL {
+            return;
+        }
+        info.send(SearchInfo {
+            depth: self.current_depth,
+            multipv: 1,
+            score: line.score,
+            pv: line.pv.clone(),
+            nodes: self.shared_counters.nodes.load(Ordering::Relaxed),
+            time,
+        });
+        self.last_report = time;
+    }
+
+    // Between iterations: stop once the soft limit has passed
+    fn out_of_time(&self) -> bool {
+        self.timed_out || self.deadline.soft_expired() || self.stop.load(Ordering::Relaxed)
+    }
+
+    // Inside the tree: abort at the hard limit, once the node budget is
+    // spent or when the depth limit drops below the iteration (a ponderhit).
+    // The budget is checked at every node, exactly for a single thread; the
+    // rest only every NODES_PER_TIME_CHECK nodes.
+    fn must_abort(&mut self) -> bool {
+        if self.timed_out {
+            return true;
+        }
+        if let Some(budget) = self.node_budget {
+            if self.shared_counters.nodes.load(Ordering::Relaxed) + self.counters.nodes >= budget {
+                self.timed_out = true;
+                return true;
+            }
+        }
+        self.counters.nodes += 1;
+        if self.counters.nodes & (NODES_PER_TIME_CHECK - 1) == 0 {
+            self.flush_counters();
+            self.timed_out = self.deadline.hard_expired()
+                || self.current_depth > self.deadline.max_depth()
+                || self.stop.load(Ordering::Relaxed);
+            self.report_progress();
+        }
+        self.timed_out
+    }
+
+    /// Completes `pv`, searched from `board` to `depth`, up to the search
+    /// horizon. The line is cut at the first illegal move, and TT best moves
+    /// are appended where the search stopped early (TT hits), as long as they
+    /// are legal and do not repeat a position of the line or the game.
+    /// Captures do not count towards the depth, as in `negamax`.
+    fn complete_pv(&self, board: &Board, player: i8, pv: Vec<Move>, depth: i32) -> Vec<Move> {
+        let mut board = *board;
+        let mut player = player;
+        let mut depth = depth;
+        let mut line = Vec::new();
+        let mut keys = vec![self.compute_hash_key(&board)];
+        let mut collisions = 0; // Not the search's, so not counted in its stats
+
+        for mv in pv {
+            if is_game_over(&board, player) || !get_valid_moves(&board, player).contains(&mv) {
+                return line;
+            }
+            if !apply_move(&mut board, player, mv) {
+                depth -= 1;
+            }
+            keys.push(self.compute_hash_key(&board));
+            line.push(mv);
+            player = -player;
+        }
+
+        while depth > 0 && !is_game_over(&board, player) {
+            let mv = match self.tt.probe(keys[keys.len() - 1], &mut collisions).and_then(|entry| entry.best_move) {
+                Some(mv) if get_valid_moves(&board, player).contains(&mv) => mv,
+                _ => break,
+            };
+            let mut next = board;
+            let capture = apply_move(&mut next, player, mv);
+            let key = self.compute_hash_key(&next);
+            if keys.contains(&key) || self.hash_history.contains(&key) {
+                break;
+            }
+            if !capture {
+                depth -= 1;
+            }
+            board = next;
+            keys.push(key);
+            line.push(mv);
+            player = -player;
+        }
+        line
+    }
+
+    fn flush_counters(&mut self) {
+        self.shared_counters.add(&self.counters);
+        self.counters = Counters::default();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        depth: i32,
+        player: i8,
+        mut alpha: i32,
+        mut beta: i32,
+        hash_key: &mut u64,
+        ply: i32,
+    ) -> Result<i32, ()> {
+        let key = *hash_key;
+        let is_root = ply == 0;
+        let old_alpha = alpha;
+        let mut old_best_move: Option<Move> = None;
+
+        // This node's line starts empty; the row below is the child's
+        let ply_index = ply as usize;
+        if self.pv_table.len() < ply_index + 2 {
+            self.pv_table.resize_with(ply_index + 2, Vec::new);
+        }
+        self.pv_table[ply_index].clear();
+
+        if self.must_abort() {
+            return Err(());
+        }
+        self.seldepth = max(self.seldepth, ply);
+
+        // Count how many times the current position has occurred in the current path
+        let repetitions = self.hash_history.iter().filter(|&&k| k == key).count();
+
+        // Check for threefold repetition; the root itself is always searched
+        if repetitions >= 3 && !is_root {
+            return Ok(draw_score(self.contempt, player, self.ai_player)); // Return a score indicating a draw
+        } else if repetitions == 1 && !is_root {
+            // Transposition Table lookup, only for positions new to the path:
+            // stored scores know nothing about repetitions
+            self.counters.tt_probes += 1;
+            if let Some(entry) = self.tt.probe(key, &mut self.counters.tt_collisions) {
+                self.counters.tt_hits += 1;
+                if entry.depth >= depth {
+                    let eval = score_from_tt(entry.eval, ply);
+                    match entry.flag {
+                        TTFlag::Exact => {
+                            // The rest of the line is recovered by complete_pv
+                            self.pv_table[ply_index].extend(entry.best_move);
+                            return Ok(eval);
+                        },
+                        TTFlag::LowerBound => alpha = max(alpha, eval),
+                        TTFlag::UpperBound => beta = min(beta, eval),
+                    }
+                    if alpha >= beta {
+                        return Ok(eval);
+                    }
+                }
+                if entry.best_move.is_some() {
+                    old_best_move = entry.best_move;
+                }
+            }
+        }
+
+        // Exact values for positions covered by the tablebase
+        if !is_root {
+            if let Some(value) = self.tablebase.as_ref().and_then(|tb| tb.probe(board, player)) {
+                return Ok(tablebase_score(value, ply, draw_score(self.contempt, player, self.ai_player)));
+            }
+        }
+
+        // The side to move has lost: the opponent reached its last row or
+        // there is no legal move
+        if is_game_over(board, player) {
+            return Ok(loss_score(ply));
+        }
+
+        // Runner races the race module proves are won, within at most
+        // `race.plies` plies
+        if !is_root {
+            if let Some(race) = race::solve(board, player) {
+                let plies = ply + race.plies as i32;
+                return Ok(if race.winner == player { bounded_win_score(plies) } else { bounded_loss_score(plies) });
+            }
+        }
+
+        if depth == 0 {
+            // Game over and races are handled above, so only the stones are left
+            let eval = -player as i32 * evaluate_stones(board, &self.eval);
+            return Ok(eval);
+        }
+
+        let mut max_eval = -i32::MAX;
+        let mut best_move: Option<Move> = None;
+
+        // Get valid moves
+        let mut moves = get_valid_moves(board, player);
+
+        if is_root {
+            // Only search the root moves the caller allows
+            moves.retain(|m| self.root_filter.allows(m));
+
+            // Sort moves based on root_move_scores
+            moves.sort_by_cached_key(|&m| {
+                // Use negative scores to sort in descending order
+                player as i32 * (self.root_move_scores.get(&m).cloned().unwrap_or(0))
+            });
+        } else {
+            // At non-root nodes, optionally use TT best move
+            if let Some(best_move_from_tt) = old_best_move {
+                if let Some(pos) = moves.iter().position(|&m| m == best_move_from_tt) {
+                    moves.swap(0, pos); // Move the best_move to the front
+                }
+            }
+        }
+
+        // Iterate over the moves
+        let mut searched = 0;
+        for m in moves {
+            if is_root && self.root_excluded.contains(&m) {
+                continue;
+            }
+            searched += 1;
+            // Make the move and update hash key
+            let capture = self.make_move(board, player, m, hash_key);
+
+            let new_depth = if capture { depth } else { depth - 1 };
+
+            let result = self.negamax(
+                board,
+                new_depth,
+                -player,
+                -beta,
+                -alpha,
+                hash_key,
+                ply + 1,
+            );
+
+            // Undo the move and restore hash key
+            self.undo_move(board, player, m, capture, hash_key);
+
+            match result {
+                Ok(eval) => {
+                    let eval = -eval;
+
+                    if eval > max_eval {
+                        max_eval = eval;
+                        best_move = Some(m);
+                        self.update_pv(ply_index, m);
+                    }
+                    alpha = max(alpha, eval);
+                    if alpha >= beta {
+                        self.counters.beta_cutoffs += 1;
+                        if searched == 1 {
+                            self.counters.first_move_cutoffs += 1;
+                        }
+                        break; // Beta cutoff
+                    }
+                },
+                Err(_) => {
+                    // Time limit reached during recursive call
+                    return Err(());
+                },
+            }
+        }
+
+        // Determine the flag for the transposition table entry
+        let flag = if max_eval <= old_alpha {
+            TTFlag::UpperBound
+        } else if max_eval >= beta {
+            TTFlag::LowerBound
+        } else {
+            TTFlag::Exact
+        };
+
+        // Store the evaluation in the transposition table, unless moves
+        // were left out of this root search
+        if !is_root || (self.root_excluded.is_empty() && self.root_filter.is_empty()) {
+            let entry = TTEntry {
+                best_move,
+                eval: score_to_tt(max_eval, ply),
+                depth,
+                flag,
+            };
+            self.tt.store(key, entry);
+        }
+
+        if let (true, Some(best_move)) = (is_root, best_move) {
+            // At root, store the move's score for ordering
+            self.root_move_scores.insert(best_move, max_eval);
+        }
+
+        Ok(max_eval)
+    }
+
+    // The line at `ply` becomes `mv` followed by the child's line
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (parents, children) = self.pv_table.split_at_mut(ply + 1);
+        let line = &mut parents[ply];
+        line.clear();
+        line.push(mv);
+        line.extend_from_slice(&children[0]);
+    }
+
+    pub fn compute_hash_key(&self, board: &Board) -> u64 {
+        zobrist_key(&self.zobrist_table, board)
+    }
+
+    fn make_move(
+        &mut self,
+        board: &mut Board,
+        player: i8,
+        mv: Move,
+        hash_key: &mut u64,
+    ) -> bool {
+        let (from_row, from_col, to_row, to_col) = mv;
+
+        let piece_index = if player == -1 { 0 } else { 1 };
+
+        // XOR out the piece from its original position
+        *hash_key ^= self.zobrist_table[from_row][from_col][piece_index];
+
+        // Remove the piece from original position
+        board[from_row][from_col] = 0;
+
+        // XOR in the piece at the new position
+        *hash_key ^= self.zobrist_table[to_row][to_col][piece_index];
+
+        // Place the piece at new position
+        board[to_row][to_col] = player;
+
+        let mut captured = false;
+
+        // Check for capture
+        if (from_row as i32 - to_row as i32).abs() == 2 {
+            let captured_row = (from_row + to_row) / 2;
+            let captured_col = (from_col + to_col) / 2;
+            let captured_piece_index = if -player == -1 { 0 } else { 1 };
+
+            // XOR out the captured piece
+            *hash_key ^= self.zobrist_table[captured_row][captured_col][captured_piece_index];
+
+            // Remove the captured piece
+            board[captured_row][captured_col] = 0;
+
+            captured = true;
+        }
+
+        // Push the new position's hash key onto the stack
+        self.hash_history.push(*hash_key);
+
+        captured
+    }
+
+    fn undo_move(
+        &mut self,
+        board: &mut Board,
+        player: i8,
+        mv: Move,
+        captured: bool,
+        hash_key: &mut u64,
+    ) {
+        let (from_row, from_col, to_row, to_col) = mv;
+
+        let piece_index = if player == -1 { 0 } else { 1 };
+
+        self.hash_history.pop(); // Remove the hash key before undoing the move
+
+        // XOR out the piece from the destination position
+        *hash_key ^= self.zobrist_table[to_row][to_col][piece_index];
+
+        // Remove the piece from the destination position
+        board[to_row][to_col] = 0;
+
+        // XOR in the piece at the original position
+        *hash_key ^= self.zobrist_table[from_row][from_col][piece_index];
+
+        // Place the piece back at the original position
+        board[from_row][from_col] = player;
+
+        if captured {
+            let captured_row = (from_row + to_row) / 2;
+            let captured_col = (from_col + to_col) / 2;
+            let captured_piece_index = if -player == -1 { 0 } else { 1 };
+
+            // XOR in the captured piece
+            *hash_key ^= self.zobrist_table[captured_row][captured_col][captured_piece_index];
+
+            // Restore the captured piece
+            board[captured_row][captured_col] = -player;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DRAW_SCORE;
+
+    #[test]
+    fn tt_scores_keep_the_distance_from_the_entry() {
+        // A win 3 plies below a node at ply 5, later met at ply 2
+        let stored = score_to_tt(win_score(8), 5);
+        assert_eq!(stored, win_score(3));
+        assert_eq!(score_from_tt(stored, 2), win_score(5));
+
+        let stored = score_to_tt(loss_score(8), 5);
+        assert_eq!(stored, loss_score(3));
+        assert_eq!(score_from_tt(stored, 2), loss_score(5));
+
+        let stored = score_to_tt(bounded_win_score(8), 5);
+        assert_eq!(score_from_tt(stored, 2), bounded_win_score(5));
+        assert!(is_decisive(bounded_win_score(MAX_PLY)));
+        assert!(bounded_win_score(1) < win_score(MAX_PLY));
+    }
+
+    #[test]
+    fn tt_scores_leave_evaluations_alone() {
+        for score in [0, DRAW_SCORE, 50_000, -FORCED_SCORE + 1] {
+            assert_eq!(score_from_tt(score_to_tt(score, 7), 3), score);
+        }
+    }
+}
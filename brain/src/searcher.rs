@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use crate::timeman::TimeLimits;
+use crate::{Board, Move};
+
+/// Outcome of a search: the score from White's point of view (positive is
+/// good for White, as in `evaluate_board`) and the principal variation.
+/// `lines` holds the best root moves, best first, when more than one was
+/// asked for; its first entry is always the main line. `stats` covers the
+/// whole search and `iterations` each completed iteration (alpha-beta only).
+#[derive(Debug, Clone)]
+pub(crate) struct SearchResult {
+    pub score: i32,
+    pub pv: Vec<Move>,
+    pub lines: Vec<PvLine>,
+    pub stats: SearchStats,
+    pub iterations: Vec<SearchStats>,
+}
+
+impl SearchResult {
+    /// Result with the main line only.
+    pub fn single(score: i32, pv: Vec<Move>, depth: i32) -> Self {
+        SearchResult {
+            score,
+            pv: pv.clone(),
+            lines: vec![PvLine { score, pv, depth }],
+            stats: SearchStats::default(),
+            iterations: Vec::new(),
+        }
+    }
+
+    /// Result made of several lines; the first one is the main line.
+    pub fn from_lines(lines: Vec<PvLine>) -> Option<Self> {
+        let main = lines.first()?;
+        Some(SearchResult {
+            score: main.score,
+            pv: main.pv.clone(),
+            lines,
+            stats: SearchStats::default(),
+            iterations: Vec::new(),
+        })
+    }
+
+    pub fn with_stats(mut self, stats: SearchStats, iterations: Vec<SearchStats>) -> Self {
+        self.stats = stats;
+        self.iterations = iterations;
+        self
+    }
+}
+
+/// One root move of a multi-PV search with its own principal variation,
+/// scored from White's point of view, and the depth it was searched to.
+#[derive(Debug, Clone)]
+pub(crate) struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+    pub depth: i32,
+}
+
+/// Counters of a search, or of the search up to the end of one iteration.
+/// `nodes` counts positions over all threads (playouts for MCTS), `seldepth`
+/// is the deepest ply reached including capture extensions, `tt_collisions`
+/// the entries of other positions that probes met and rejected by their
+/// check bits, and `hashfull` the share of the transposition table in use,
+/// in permille.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchStats {
+    pub depth: i32,
+    pub seldepth: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_collisions: u64,
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64, // Cutoffs by the first move searched, a measure of move ordering
+    pub hashfull: u32,
+}
+
+impl SearchStats {
+    pub fn nps(&self) -> u64 {
+        (self.nodes * 1000).checked_div(self.time.as_millis() as u64).unwrap_or(0)
+    }
+
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        if self.beta_cutoffs == 0 {
+            0.0
+        } else {
+            self.first_move_cutoffs as f64 / self.beta_cutoffs as f64
+        }
+    }
+}
+
+/// What ends a search: the depth, the clock and the node budget, whichever
+/// comes first. A deterministic search runs on one thread from an empty
+/// transposition table with fixed seeds and never reads the clock, so the
+/// same position and limits always give the same move, score and node count.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SearchLimits {
+    pub max_depth: i32,
+    pub time: Option<TimeLimits>,
+    pub nodes: Option<u64>,
+    pub deterministic: bool,
+}
+
+impl SearchLimits {
+    /// Depth and time limits only, as for `get_best_move`.
+    pub fn timed(max_depth: i32, time: TimeLimits) -> Self {
+        SearchLimits { max_depth, time: Some(time), nodes: None, deterministic: false }
+    }
+
+    /// The time limits to apply; a deterministic search has none.
+    pub fn clock(&self) -> Option<TimeLimits> {
+        if self.deterministic {
+            None
+        } else {
+            self.time
+        }
+    }
+}
+
+/// Root moves a search is allowed to play: only `include` when it is not
+/// empty, and never anything in `exclude`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RootFilter {
+    pub include: Vec<Move>,
+    pub exclude: Vec<Move>,
+}
+
+impl RootFilter {
+    pub fn allows(&self, mv: &Move) -> bool {
+        (self.include.is_empty() || self.include.contains(mv)) && !self.exclude.contains(mv)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// Common interface of the search algorithms `FiancoAI` can play with.
+pub(crate) trait Searcher: Send {
+    /// Searches `board` for `player` within the given limits. Returns `None`
+    /// when the side to move has no legal move.
+    fn search(&mut self, board: &Board, player: i8, limits: &SearchLimits) -> Option<SearchResult>;
+}
+
+/// Search algorithms selectable from Python.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    AlphaBeta,
+    Mcts,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "alphabeta" | "alpha-beta" | "negamax" => Some(Algorithm::AlphaBeta),
+            "mcts" => Some(Algorithm::Mcts),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::AlphaBeta => "alphabeta",
+            Algorithm::Mcts => "mcts",
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::searcher::{SearchLimits, SearchResult};
+
+pub(crate) const MAX_SKILL: u32 = 20; // Full strength: the engine plays its best move
+pub(crate) const SKILL_LINES: usize = 4; // Root moves a weakened engine chooses from
+const MIN_ELO: i32 = 800; // Calibration target of level 0
+const ELO_PER_LEVEL: i32 = 75; // Level 20 targets 2300
+const MAX_TEMPERATURE: f64 = 200.0; // At level 0, in evaluation units (20 per stone)
+const MAX_SHALLOW_CHANCE: f64 = 0.5; // At level 0, the share of moves from a shallow search
+
+/// Playing strength below full: at `level` under `MAX_SKILL` the search is
+/// capped in depth, now and then runs much shallower still, and the move is
+/// drawn from the best `SKILL_LINES` root moves with weights
+/// `exp((score - best) / temperature)`, the temperature growing as the level
+/// drops. Each level has an Elo target to calibrate against in arena games.
+pub(crate) struct Skill {
+    pub level: u32,
+    rng: StdRng,
+}
+
+impl Skill {
+    pub fn new() -> Self {
+        Skill { level: MAX_SKILL, rng: StdRng::from_entropy() }
+    }
+
+    pub fn is_full_strength(&self) -> bool {
+        self.level >= MAX_SKILL
+    }
+
+    /// The level whose Elo target is closest to `elo`.
+    pub fn level_for_elo(elo: i32) -> u32 {
+        let level = (elo - MIN_ELO + ELO_PER_LEVEL / 2).div_euclid(ELO_PER_LEVEL);
+        level.clamp(0, MAX_SKILL as i32) as u32
+    }
+
+    /// The Elo target of `level`.
+    pub fn elo_for_level(level: u32) -> i32 {
+        MIN_ELO + level.min(MAX_SKILL) as i32 * ELO_PER_LEVEL
+    }
+
+    pub fn elo(&self) -> i32 {
+        Skill::elo_for_level(self.level)
+    }
+
+    // Share of strength missing, 0 at full strength and 1 at level 0
+    fn weakness(&self) -> f64 {
+        (MAX_SKILL - self.level.min(MAX_SKILL)) as f64 / MAX_SKILL as f64
+    }
+
+    /// The limits to search with at this level. A deterministic search
+    /// restarts the random generator from a fixed seed first, so the chosen
+    /// move stays reproducible.
+    pub fn limits(&mut self, limits: &SearchLimits) -> SearchLimits {
+        if limits.deterministic {
+            self.rng = StdRng::seed_from_u64(0);
+        }
+        if self.is_full_strength() {
+            return *limits;
+        }
+        let max_depth = if self.rng.gen_bool(MAX_SHALLOW_CHANCE * self.weakness()) {
+            1 + self.level as i32 / 8
+        } else {
+            2 + self.level as i32 / 2
+        };
+        SearchLimits { max_depth: limits.max_depth.min(max_depth), ..*limits }
+    }
+
+    /// Draws the move to play from the lines of `result`, searched for
+    /// `player`, and returns the result with the chosen line first and at
+    /// most `lines` lines.
+    pub fn choose(&mut self, mut result: SearchResult, player: i8, lines: usize) -> SearchResult {
+        if !self.is_full_strength() && result.lines.len() > 1 {
+            let temperature = MAX_TEMPERATURE * self.weakness();
+            // Scores from the point of view of the side to move
+            let scores: Vec<f64> = result.lines.iter().map(|line| -player as f64 * line.score as f64).collect();
+            let best = scores.iter().cloned().fold(f64::MIN, f64::max);
+            let weights: Vec<f64> = scores.iter().map(|score| ((score - best) / temperature).exp()).collect();
+            let mut pick = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+            let mut chosen = weights.len() - 1;
+            for (i, weight) in weights.iter().enumerate() {
+                if pick < *weight {
+                    chosen = i;
+                    break;
+                }
+                pick -= weight;
+            }
+            let line = result.lines.remove(chosen);
+            result.score = line.score;
+            result.pv = line.pv.clone();
+            result.lines.insert(0, line);
+        }
+        result.lines.truncate(lines.max(1));
+        result
+    }
+}
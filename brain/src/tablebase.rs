@@ -0,0 +1,375 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::fileformat::{invalid_data, read_magic};
+use crate::{apply_move, get_valid_moves, is_winner, Board, Move, COLS, ROWS};
+
+const SQUARES: usize = ROWS * COLS;
+// Per side. Two take about 21 MB; three would be C(81,3)^2, about 7.3e9
+// positions (14.5 GB), past what memory and the u32 indices allow
+pub(crate) const MAX_TB_STONES: usize = 2;
+const MAGIC: &[u8; 4] = b"FTB1";
+
+// One byte per position: the distance to the result in plies, where an even
+// distance is a loss and an odd one a win for the side to move.
+const DRAW: u8 = 255;
+const INVALID: u8 = 254;
+const UNRESOLVED: u8 = 253; // Only used during generation
+
+/// Exact value of a tablebase position for the side to move, with the
+/// distance to the end of the game in plies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TbValue {
+    Win(u8),
+    Loss(u8),
+    Draw,
+}
+
+impl TbValue {
+    fn decode(value: u8) -> Option<Self> {
+        match value {
+            DRAW => Some(TbValue::Draw),
+            INVALID | UNRESOLVED => None,
+            d if d & 1 == 0 => Some(TbValue::Loss(d)),
+            d => Some(TbValue::Win(d)),
+        }
+    }
+}
+
+const fn binomial_table() -> [[u64; MAX_TB_STONES + 1]; SQUARES + 1] {
+    let mut table = [[0u64; MAX_TB_STONES + 1]; SQUARES + 1];
+    let mut n = 0;
+    while n <= SQUARES {
+        table[n][0] = 1;
+        let mut k = 1;
+        while k <= MAX_TB_STONES {
+            table[n][k] = if n == 0 { 0 } else { table[n - 1][k - 1] + table[n - 1][k] };
+            k += 1;
+        }
+        n += 1;
+    }
+    table
+}
+
+static BINOMIAL: [[u64; MAX_TB_STONES + 1]; SQUARES + 1] = binomial_table();
+
+// Rank of a sorted set of squares in the combinatorial number system
+fn rank(squares: &[usize]) -> u64 {
+    squares.iter().enumerate().map(|(i, &s)| BINOMIAL[s][i + 1]).sum()
+}
+
+fn unrank(mut rank: u64, squares: &mut [usize]) {
+    for i in (0..squares.len()).rev() {
+        let mut s = i;
+        while BINOMIAL[s + 1][i + 1] <= rank {
+            s += 1;
+        }
+        rank -= BINOMIAL[s][i + 1];
+        squares[i] = s;
+    }
+}
+
+// Squares of each side in ascending order, or None if a side has more stones than fit
+fn stone_squares(board: &Board, max_stones: usize) -> Option<(Vec<usize>, Vec<usize>)> {
+    let mut white = Vec::with_capacity(max_stones);
+    let mut black = Vec::with_capacity(max_stones);
+    for (square, &piece) in board.iter().flatten().enumerate() {
+        let side = match piece {
+            -1 => &mut white,
+            1 => &mut black,
+            _ => continue,
+        };
+        if side.len() == max_stones {
+            return None;
+        }
+        side.push(square);
+    }
+    Some((white, black))
+}
+
+/// Retrograde-analysis endgame tablebase covering every position with up to
+/// `max_stones` stones per side, for both sides to move.
+pub(crate) struct Tablebase {
+    max_stones: usize,
+    tables: Vec<Vec<u8>>, // One table per (white stones, black stones, side to move)
+}
+
+impl Tablebase {
+    fn table_id(&self, white: usize, black: usize, player: i8) -> usize {
+        ((white - 1) * self.max_stones + (black - 1)) * 2 + if player == 1 { 1 } else { 0 }
+    }
+
+    fn lookup(&self, white: &[usize], black: &[usize], player: i8) -> u8 {
+        let to_move = if player == -1 { white } else { black };
+        if to_move.is_empty() {
+            return 0; // No stones left: lost
+        }
+        let table = &self.tables[self.table_id(white.len(), black.len(), player)];
+        table[(rank(white) * BINOMIAL[SQUARES][black.len()] + rank(black)) as usize]
+    }
+
+    /// Looks up the position, if it is covered by the tablebase.
+    pub fn probe(&self, board: &Board, player: i8) -> Option<TbValue> {
+        let (white, black) = stone_squares(board, self.max_stones)?;
+        let opponent = if player == -1 { &black } else { &white };
+        if opponent.is_empty() {
+            return None; // Not reachable: the game ended with the last capture
+        }
+        TbValue::decode(self.lookup(&white, &black, player))
+    }
+
+    /// Picks the move that keeps the tablebase value: the quickest win, a
+    /// drawing move, or the slowest loss.
+    pub fn best_move(&self, board: &Board, player: i8) -> Option<(TbValue, Move)> {
+        let value = self.probe(board, player)?;
+        let mut best: Option<(Move, u8)> = None;
+        for mv in get_valid_moves(board, player) {
+            let mut child = *board;
+            apply_move(&mut child, player, mv);
+            let (white, black) = stone_squares(&child, self.max_stones)?;
+            let child_value = TbValue::decode(self.lookup(&white, &black, -player));
+            let rank = match (value, child_value) {
+                // Prefer the quickest win and the slowest loss
+                (TbValue::Win(_), Some(TbValue::Loss(d))) => u8::MAX - d,
+                (TbValue::Loss(_), Some(TbValue::Win(d))) => d,
+                (TbValue::Draw, Some(TbValue::Draw)) => 0,
+                _ => continue,
+            };
+            let better = match best {
+                Some((_, best_rank)) => rank > best_rank,
+                None => true,
+            };
+            if better {
+                best = Some((mv, rank));
+            }
+        }
+        best.map(|(mv, _)| (value, mv))
+    }
+
+    /// Builds the tablebase by retrograde analysis, smallest material first.
+    pub fn generate(max_stones: usize) -> Self {
+        let max_stones = max_stones.clamp(1, MAX_TB_STONES);
+        let mut tablebase = Tablebase {
+            max_stones,
+            tables: vec![Vec::new(); max_stones * max_stones * 2],
+        };
+        for total in 2..=2 * max_stones {
+            for white in 1..=max_stones {
+                if total <= white || total - white > max_stones {
+                    continue;
+                }
+                tablebase.generate_class(white, total - white);
+            }
+        }
+        tablebase
+    }
+
+    // Solves all positions with `white` and `black` stones. Captures lead to
+    // smaller classes, which are complete by the time this one is built.
+    fn generate_class(&mut self, white: usize, black: usize) {
+        let black_sets = BINOMIAL[SQUARES][black];
+        let size = (BINOMIAL[SQUARES][white] * black_sets) as usize;
+        let mut tables = [vec![UNRESOLVED; size], vec![UNRESOLVED; size]];
+        let players = [-1i8, 1i8];
+
+        let mut white_squares = vec![0; white];
+        let mut black_squares = vec![0; black];
+        let mut decode = |index: usize, board: &mut Board| -> bool {
+            unrank(index as u64 / black_sets, &mut white_squares);
+            unrank(index as u64 % black_sets, &mut black_squares);
+            *board = [[0; COLS]; ROWS];
+            for &s in white_squares.iter() {
+                board[s / COLS][s % COLS] = -1;
+            }
+            for &s in black_squares.iter() {
+                if board[s / COLS][s % COLS] != 0 {
+                    return false; // Overlapping stones
+                }
+                board[s / COLS][s % COLS] = 1;
+            }
+            true
+        };
+
+        // Mark impossible and terminal positions
+        let mut unresolved: [Vec<u32>; 2] = [Vec::new(), Vec::new()];
+        let mut board: Board = [[0; COLS]; ROWS];
+        let [white_table, black_table] = &mut tables;
+        for (index, (white_cell, black_cell)) in white_table.iter_mut().zip(black_table.iter_mut()).enumerate() {
+            let valid = decode(index, &mut board);
+            for (side, cell) in [white_cell, black_cell].into_iter().enumerate() {
+                let player = players[side];
+                *cell = if !valid || is_winner(&board, player) {
+                    INVALID
+                } else if is_winner(&board, -player) || get_valid_moves(&board, player).is_empty() {
+                    0
+                } else {
+                    unresolved[side].push(index as u32);
+                    UNRESOLVED
+                };
+            }
+        }
+
+        // Positions in smaller classes may be reached through captures, so keep
+        // going at least until their longest distance is covered
+        let horizon = self
+            .tables
+            .iter()
+            .flatten()
+            .filter(|&&v| v < UNRESOLVED)
+            .max()
+            .map_or(0, |&v| v as u32 + 1);
+
+        // Pass k assigns exactly the positions at distance k: wins that reach a
+        // loss in k - 1, and losses where every move reaches a win
+        let mut k: u32 = 1;
+        let mut idle_passes = 0;
+        while idle_passes < 2 || k <= horizon + 1 {
+            assert!(k < UNRESOLVED as u32, "tablebase distance overflow");
+            let mut changed = false;
+            for side in 0..2 {
+                let player = players[side];
+                let mut still_unresolved = Vec::new();
+                for &index in unresolved[side].iter() {
+                    decode(index as usize, &mut board);
+                    let mut found_win = false;
+                    let mut all_wins = true;
+                    for mv in get_valid_moves(&board, player) {
+                        let mut child = board;
+                        apply_move(&mut child, player, mv);
+                        let value = self.child_value(&tables, &child, -player, white, black) as u32;
+                        if value == k - 1 && value & 1 == 0 {
+                            found_win = true;
+                            break;
+                        }
+                        // Captures may reach wins further away than this pass
+                        if value >= UNRESOLVED as u32 || value & 1 == 0 || value >= k {
+                            all_wins = false;
+                        }
+                    }
+                    if (k & 1 == 1 && found_win) || (k & 1 == 0 && all_wins) {
+                        tables[side][index as usize] = k as u8;
+                        changed = true;
+                    } else {
+                        still_unresolved.push(index);
+                    }
+                }
+                unresolved[side] = still_unresolved;
+            }
+            idle_passes = if changed { 0 } else { idle_passes + 1 };
+            k += 1;
+        }
+
+        // Whatever is left can never be forced by either side
+        for side in 0..2 {
+            for &index in unresolved[side].iter() {
+                tables[side][index as usize] = DRAW;
+            }
+        }
+
+        let [white_to_move, black_to_move] = tables;
+        let white_id = self.table_id(white, black, -1);
+        let black_id = self.table_id(white, black, 1);
+        self.tables[white_id] = white_to_move;
+        self.tables[black_id] = black_to_move;
+    }
+
+    // Value of a child position, looked up in the class being built or, after
+    // a capture, in an already finished smaller class
+    fn child_value(&self, tables: &[Vec<u8>; 2], child: &Board, player: i8, white: usize, black: usize) -> u8 {
+        let (white_squares, black_squares) = stone_squares(child, self.max_stones).expect("stone count grew");
+        if white_squares.len() == white && black_squares.len() == black {
+            let index = rank(&white_squares) * BINOMIAL[SQUARES][black] + rank(&black_squares);
+            tables[if player == -1 { 0 } else { 1 }][index as usize]
+        } else {
+            self.lookup(&white_squares, &black_squares, player)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[self.max_stones as u8])?;
+        for table in &self.tables {
+            writer.write_all(&(table.len() as u64).to_le_bytes())?;
+            writer.write_all(table)?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        read_magic(&mut reader, MAGIC, "tablebase")?;
+        let mut max_stones = [0u8; 1];
+        reader.read_exact(&mut max_stones)?;
+        let max_stones = max_stones[0] as usize;
+        if max_stones == 0 || max_stones > MAX_TB_STONES {
+            return Err(invalid_data("unsupported tablebase size"));
+        }
+
+        let mut tablebase = Tablebase { max_stones, tables: Vec::new() };
+        let class_sizes = &BINOMIAL[SQUARES][1..=max_stones];
+        for &white_sets in class_sizes {
+            for &black_sets in class_sizes {
+                let expected = (white_sets * black_sets) as usize;
+                for _ in 0..2 {
+                    let mut len = [0u8; 8];
+                    reader.read_exact(&mut len)?;
+                    if u64::from_le_bytes(len) as usize != expected {
+                        return Err(invalid_data("corrupt tablebase file"));
+                    }
+                    let mut table = vec![0u8; expected];
+                    reader.read_exact(&mut table)?;
+                    tablebase.tables.push(table);
+                }
+            }
+        }
+        Ok(tablebase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // White (-1) one step from row 0, Black (1) far from row 8
+    fn runner_board() -> Board {
+        let mut board = [[0; COLS]; ROWS];
+        board[1][4] = -1;
+        board[3][0] = 1;
+        board
+    }
+
+    #[test]
+    fn known_results_have_their_distance() {
+        let tablebase = Tablebase::generate(1);
+        let board = runner_board();
+        assert_eq!(tablebase.probe(&board, -1), Some(TbValue::Win(1)));
+        assert_eq!(tablebase.probe(&board, 1), Some(TbValue::Loss(2)));
+        let (_, best_move) = tablebase.best_move(&board, -1).unwrap();
+        assert_eq!(best_move.2, 0);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let tablebase = Tablebase::generate(1);
+        let path = std::env::temp_dir().join(format!("fianco_tb_{}.bin", std::process::id()));
+        tablebase.save(&path).unwrap();
+        let loaded = Tablebase::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.max_stones, tablebase.max_stones);
+        assert!(loaded.tables == tablebase.tables);
+        let board = runner_board();
+        assert_eq!(loaded.probe(&board, 1), tablebase.probe(&board, 1));
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let path = std::env::temp_dir().join(format!("fianco_tb_old_{}.bin", std::process::id()));
+        std::fs::write(&path, b"FTB0\x01").unwrap();
+        let error = Tablebase::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
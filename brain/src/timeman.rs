@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const MOVE_OVERHEAD_MS: u64 = 50; // Kept back per move for the GUI/Python round trip
+const DEFAULT_MOVES_TO_GO: u64 = 30; // Moves the remaining time is spread over in sudden death
+const HARD_FACTOR: u64 = 4; // The hard limit may stretch the soft one this much...
+const MAX_SHARE_PERCENT: u64 = 40; // ...but never beyond this share of the remaining time
+const NO_LIMIT_MS: u64 = u64::MAX;
+
+/// Time allowed for one move. No new iteration starts after `soft`; a
+/// running one is aborted at `hard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimeLimits {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl TimeLimits {
+    /// A fixed time per move, used as both limits.
+    pub fn fixed(max_time: Duration) -> Self {
+        TimeLimits { soft: max_time, hard: max_time }
+    }
+
+    /// Limits for a game clock: `remaining_ms` left on the clock,
+    /// `increment_ms` added after each move and `moves_to_go` until the next
+    /// time control (0 for sudden death). The hard limit always leaves
+    /// `MOVE_OVERHEAD_MS` on the clock, so the engine never loses on time.
+    pub fn from_clock(remaining_ms: u64, increment_ms: u64, moves_to_go: u64) -> Self {
+        let available = remaining_ms.saturating_sub(MOVE_OVERHEAD_MS);
+        let moves = if moves_to_go == 0 { DEFAULT_MOVES_TO_GO } else { moves_to_go };
+
+        let soft = available / moves + increment_ms * 3 / 4;
+        let max_share = if moves == 1 { available } else { available * MAX_SHARE_PERCENT / 100 };
+        let hard = (soft * HARD_FACTOR).min(max_share);
+        TimeLimits {
+            soft: Duration::from_millis(soft.min(hard)),
+            hard: Duration::from_millis(hard),
+        }
+    }
+}
+
+/// Soft and hard deadlines of a running search in milliseconds from its
+/// start, and the deepest iteration it may start, shared by all search
+/// threads. They can be moved while the search runs, which turns a ponder
+/// search into the real one.
+pub(crate) struct Deadline {
+    start: Instant,
+    soft_ms: AtomicU64,
+    hard_ms: AtomicU64,
+    max_depth: AtomicI32,
+}
+
+impl Deadline {
+    pub fn new(limits: TimeLimits) -> Self {
+        Deadline {
+            start: Instant::now(),
+            soft_ms: AtomicU64::new(limits.soft.as_millis() as u64),
+            hard_ms: AtomicU64::new(limits.hard.as_millis() as u64),
+            max_depth: AtomicI32::new(i32::MAX),
+        }
+    }
+
+    /// A deadline that never expires (pondering).
+    pub fn unlimited() -> Self {
+        Deadline {
+            start: Instant::now(),
+            soft_ms: AtomicU64::new(NO_LIMIT_MS),
+            hard_ms: AtomicU64::new(NO_LIMIT_MS),
+            max_depth: AtomicI32::new(i32::MAX),
+        }
+    }
+
+    /// Restarts the limits from now.
+    pub fn reset(&self, limits: TimeLimits) {
+        let elapsed = self.elapsed_ms();
+        self.soft_ms.store(elapsed + limits.soft.as_millis() as u64, Ordering::Relaxed);
+        self.hard_ms.store(elapsed + limits.hard.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Lets no iteration deeper than `max_depth` start.
+    pub fn limit_depth(&self, max_depth: i32) {
+        self.max_depth.fetch_min(max_depth, Ordering::Relaxed);
+    }
+
+    pub fn max_depth(&self) -> i32 {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    pub fn soft_expired(&self) -> bool {
+        self.elapsed_ms() >= self.soft_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn hard_expired(&self) -> bool {
+        self.elapsed_ms() >= self.hard_ms.load(Ordering::Relaxed)
+    }
+}
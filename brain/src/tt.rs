@@ -0,0 +1,416 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::fileformat::{invalid_data, read_magic};
+use crate::{Move, COLS, FORCED_SCORE, MAX_SCORE, ROWS};
+
+// Define the possible flags for entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TTFlag {
+    Exact,
+    LowerBound,
+    UpperBound,
+}
+
+// Structure for a transposition table entry
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TTEntry {
+    pub best_move: Option<Move>,
+    pub eval: i32,
+    pub depth: i32,
+    pub flag: TTFlag,
+}
+
+// Bit layout of a packed entry (low to high):
+//   0..14  best move, from and to square (`row * 9 + col`)
+//   14     best move present
+//   16..32 eval, compressed to an i16 by `compress_score`
+//   32..40 depth
+//   40..42 flag
+//   42..46 generation of the search that stored it
+//   46     slot in use
+//   48..64 check bits: the top bits of the key
+const MOVE_PRESENT: u64 = 1 << 14;
+const SLOT_USED: u64 = 1 << 46;
+const GENERATION_SHIFT: u64 = 42;
+const GENERATIONS: u8 = 16; // Generations wrap around after this many searches
+const CHECK_SHIFT: u64 = 48;
+
+// Scores from FORCED_SCORE up (forced results, races included) keep their
+// distance in the top band of an i16; any other score is clamped to the
+// range below it
+const FORCED_BAND: i32 = i16::MAX as i32 - (MAX_SCORE - FORCED_SCORE);
+const EVAL_LIMIT: i32 = FORCED_BAND - 1;
+
+fn compress_score(score: i32) -> i16 {
+    let abs = score.abs().min(MAX_SCORE);
+    let compressed = if abs >= FORCED_SCORE {
+        FORCED_BAND + abs - FORCED_SCORE
+    } else {
+        abs.min(EVAL_LIMIT)
+    };
+    (score.signum() * compressed) as i16
+}
+
+fn expand_score(compressed: i16) -> i32 {
+    let abs = (compressed as i32).abs();
+    let score = if abs >= FORCED_BAND {
+        FORCED_SCORE + abs - FORCED_BAND
+    } else {
+        abs
+    };
+    (compressed as i32).signum() * score
+}
+
+impl TTEntry {
+    fn pack(&self) -> u64 {
+        let mut data = SLOT_USED;
+        if let Some((from_row, from_col, to_row, to_col)) = self.best_move {
+            data |= (from_row * COLS + from_col) as u64 | ((to_row * COLS + to_col) as u64) << 7 | MOVE_PRESENT;
+        }
+        data |= (compress_score(self.eval) as u16 as u64) << 16;
+        data |= (self.depth.clamp(0, u8::MAX as i32) as u64) << 32;
+        let flag = match self.flag {
+            TTFlag::Exact => 0,
+            TTFlag::LowerBound => 1,
+            TTFlag::UpperBound => 2,
+        };
+        data |= flag << 40;
+        data
+    }
+
+    fn unpack(data: u64) -> Self {
+        let best_move = if data & MOVE_PRESENT != 0 {
+            let (from, to) = ((data & 0x7F) as usize, ((data >> 7) & 0x7F) as usize);
+            Some((from / COLS, from % COLS, to / COLS, to % COLS))
+        } else {
+            None
+        };
+        let flag = match (data >> 40) & 0x3 {
+            0 => TTFlag::Exact,
+            1 => TTFlag::LowerBound,
+            _ => TTFlag::UpperBound,
+        };
+        TTEntry {
+            best_move,
+            eval: expand_score((data >> 16) as u16 as i16),
+            depth: ((data >> 32) & 0xFF) as i32,
+            flag,
+        }
+    }
+}
+
+// A slot is a single word: the packed entry with the check bits of its
+// key. The bucket stands for the low bits of the key and the check bits
+// for the top ones, so a probe only accepts an entry whose check bits
+// match; one word is written at once, so threads never see a torn entry.
+struct TTSlot(AtomicU64);
+
+impl TTSlot {
+    // The packed entry held, if the slot is in use
+    fn read(&self) -> Option<u64> {
+        let data = self.0.load(Ordering::Relaxed);
+        (data & SLOT_USED != 0).then_some(data)
+    }
+}
+
+fn generation(data: u64) -> u8 {
+    ((data >> GENERATION_SHIFT) % GENERATIONS as u64) as u8
+}
+
+fn check_bits(key: u64) -> u64 {
+    key >> CHECK_SHIFT
+}
+
+const MAGIC: &[u8; 4] = b"FTT2"; // Of saved tables
+const ENTRY_BYTES: usize = 16; // Known key bits, from and to square, eval, depth and flag
+const NO_SQUARE: u8 = u8::MAX; // From square of an entry without a best move
+
+const BUCKET_SLOTS: usize = 2; // A depth-preferred slot, then an always-replace slot
+const BYTES_PER_MB: usize = 1 << 20;
+
+/// Lock-free transposition table shared by every search thread, allocated
+/// once at a fixed size of 8-byte entries. Each bucket holds an entry kept
+/// while it is the deepest seen for its bucket and one replaced on every
+/// other store, so deep results survive a flood of shallow ones without
+/// blocking new ones.
+/// Entries remember the generation, bumped per search, that stored them:
+/// the depth-preferred entry of an earlier search gives way to any new one,
+/// so stale entries are replaced gradually instead of all at once.
+pub(crate) struct SharedTT {
+    slots: Vec<TTSlot>,
+    mask: usize, // Bucket index bits of a key
+    used: AtomicUsize,
+    generation: AtomicU8,
+}
+
+impl SharedTT {
+    /// Table of at most `megabytes` MB (at least one), rounded down to a
+    /// power of two of buckets.
+    pub fn new(megabytes: usize) -> Self {
+        let bucket_bytes = BUCKET_SLOTS * std::mem::size_of::<TTSlot>();
+        let max_buckets = (megabytes.max(1) * BYTES_PER_MB / bucket_bytes).max(1);
+        let num_buckets = if max_buckets.is_power_of_two() { max_buckets } else { max_buckets.next_power_of_two() / 2 };
+        let slots = (0..num_buckets * BUCKET_SLOTS)
+            .map(|_| TTSlot(AtomicU64::new(0)))
+            .collect();
+        SharedTT {
+            slots,
+            mask: num_buckets - 1,
+            used: AtomicUsize::new(0),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    fn bucket(&self, key: u64) -> &[TTSlot] {
+        let first = (key as usize & self.mask) * BUCKET_SLOTS;
+        &self.slots[first..first + BUCKET_SLOTS]
+    }
+
+    /// The entry of the bucket whose check bits match `key`. Entries of
+    /// other positions in the bucket are added to `collisions`; one in 65536
+    /// of them shares the check bits and is taken for the position, so
+    /// callers check a best move for legality before playing it.
+    pub fn probe(&self, key: u64, collisions: &mut u64) -> Option<TTEntry> {
+        for data in self.bucket(key).iter().filter_map(TTSlot::read) {
+            if check_bits(data) == check_bits(key) {
+                return Some(TTEntry::unpack(data));
+            }
+            *collisions += 1;
+        }
+        None
+    }
+
+    /// Starts a new generation; call once per search.
+    pub fn new_search(&self) {
+        let next = (self.generation.load(Ordering::Relaxed) + 1) % GENERATIONS;
+        self.generation.store(next, Ordering::Relaxed);
+    }
+
+    /// Stores into the depth-preferred slot when it is empty, holds the same
+    /// position, an entry of an earlier generation or one searched no
+    /// deeper; otherwise into the always-replace slot.
+    pub fn store(&self, key: u64, entry: TTEntry) {
+        let bucket = self.bucket(key);
+        let current = self.generation.load(Ordering::Relaxed);
+        let replace_deep = match bucket[0].read() {
+            Some(data) => {
+                check_bits(data) == check_bits(key) || generation(data) != current || entry.depth >= TTEntry::unpack(data).depth
+            },
+            None => true,
+        };
+        let slot = if replace_deep { &bucket[0] } else { &bucket[1] };
+        let data = entry.pack() | (current as u64) << GENERATION_SHIFT | check_bits(key) << CHECK_SHIFT;
+        if slot.0.swap(data, Ordering::Relaxed) & SLOT_USED == 0 {
+            self.used.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Empties every slot.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.0.store(0, Ordering::Relaxed);
+        }
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    /// Number of slots in use.
+    pub fn len(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Allocated size in megabytes.
+    pub fn megabytes(&self) -> usize {
+        self.slots.len() * std::mem::size_of::<TTSlot>() / BYTES_PER_MB
+    }
+
+    /// Saves every entry searched at least `min_depth` deep and returns how
+    /// many. Entries keep only part of their key, so the header gives the
+    /// number of buckets after the entry count, and the file only loads into
+    /// a table with at most that many. On disk each entry is `ENTRY_BYTES` long:
+    /// the key bits the table knows (the bucket index, and the check bits in
+    /// the top 16 bits), the from and to squares of the best move
+    /// (`row * 9 + col`, or 255 for none), the eval, the depth and the flag,
+    /// all little endian.
+    pub fn save(&self, path: &Path, min_depth: i32) -> io::Result<usize> {
+        let mut entries = Vec::new();
+        for (index, bucket) in self.slots.chunks(BUCKET_SLOTS).enumerate() {
+            let mut keys = Vec::with_capacity(BUCKET_SLOTS);
+            for data in bucket.iter().filter_map(TTSlot::read) {
+                let key = check_bits(data) << CHECK_SHIFT | index as u64;
+                let entry = TTEntry::unpack(data);
+                // An older copy of the position can linger in the second slot
+                if entry.depth >= min_depth && !keys.contains(&key) {
+                    entries.push((key, entry));
+                }
+                keys.push(key);
+            }
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+        writer.write_all(&((self.mask + 1) as u64).to_le_bytes())?;
+        for (key, entry) in &entries {
+            let (from, to) = match entry.best_move {
+                Some((from_row, from_col, to_row, to_col)) => {
+                    ((from_row * COLS + from_col) as u8, (to_row * COLS + to_col) as u8)
+                },
+                None => (NO_SQUARE, NO_SQUARE),
+            };
+            let flag = match entry.flag {
+                TTFlag::Exact => 0u8,
+                TTFlag::LowerBound => 1,
+                TTFlag::UpperBound => 2,
+            };
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&[from, to])?;
+            writer.write_all(&entry.eval.to_le_bytes())?;
+            writer.write_all(&[entry.depth.clamp(0, u8::MAX as i32) as u8, flag])?;
+        }
+        writer.flush()?;
+        Ok(entries.len())
+    }
+
+    /// Stores the entries of a saved table, shallowest first so the deepest
+    /// end up in the depth-preferred slots, and returns how many. They count
+    /// as results of the current search. A file saved from a smaller table
+    /// lacks key bits this one needs and is refused.
+    pub fn load(&self, path: &Path) -> io::Result<usize> {
+        let mut reader = BufReader::new(File::open(path)?);
+        read_magic(&mut reader, MAGIC, "transposition table")?;
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len) as usize;
+        let mut buckets = [0u8; 8];
+        reader.read_exact(&mut buckets)?;
+        if (u64::from_le_bytes(buckets) as usize) < self.mask + 1 {
+            return Err(invalid_data("transposition table file saved from a smaller table"));
+        }
+
+        let mut entries = Vec::new();
+        let mut bytes = [0u8; ENTRY_BYTES];
+        for _ in 0..len {
+            reader.read_exact(&mut bytes)?;
+            let key = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+            let (from, to) = (bytes[8] as usize, bytes[9] as usize);
+            let best_move = if bytes[8] == NO_SQUARE {
+                None
+            } else if from < ROWS * COLS && to < ROWS * COLS {
+                Some((from / COLS, from % COLS, to / COLS, to % COLS))
+            } else {
+                return Err(invalid_data("corrupt transposition table file"));
+            };
+            let flag = match bytes[15] {
+                0 => TTFlag::Exact,
+                1 => TTFlag::LowerBound,
+                2 => TTFlag::UpperBound,
+                _ => return Err(invalid_data("corrupt transposition table file")),
+            };
+            let eval = i32::from_le_bytes(bytes[10..14].try_into().unwrap());
+            entries.push((key, TTEntry { best_move, eval, depth: bytes[14] as i32, flag }));
+        }
+        entries.sort_by_key(|(_, entry)| entry.depth);
+        for (key, entry) in &entries {
+            self.store(*key, *entry);
+        }
+        Ok(entries.len())
+    }
+
+    /// Share of the first thousand slots used by the current generation,
+    /// in permille.
+    pub fn hashfull(&self) -> u32 {
+        let current = self.generation.load(Ordering::Relaxed);
+        let sample = &self.slots[..self.slots.len().min(1000)];
+        let used = sample.iter().filter(|slot| slot.read().is_some_and(|data| generation(data) == current)).count();
+        (used * 1000 / sample.len()) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(best_move: Option<Move>, eval: i32, depth: i32, flag: TTFlag) -> TTEntry {
+        TTEntry { best_move, eval, depth, flag }
+    }
+
+    fn assert_same(a: &TTEntry, b: &TTEntry) {
+        assert_eq!((a.best_move, a.eval, a.depth, a.flag), (b.best_move, b.eval, b.depth, b.flag));
+    }
+
+    #[test]
+    fn packed_entries_round_trip() {
+        let moves = [None, Some((0, 0, 0, 1)), Some((3, 4, 4, 4)), Some((8, 8, 7, 8))];
+        let evals = [0, 1, -250, MAX_SCORE - 7, -(MAX_SCORE - 12), FORCED_SCORE, -FORCED_SCORE];
+        for &best_move in &moves {
+            for &eval in &evals {
+                for flag in [TTFlag::Exact, TTFlag::LowerBound, TTFlag::UpperBound] {
+                    for depth in [0, 1, 17, u8::MAX as i32] {
+                        let original = entry(best_move, eval, depth, flag);
+                        assert_same(&TTEntry::unpack(original.pack()), &original);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn forced_scores_keep_their_distance() {
+        for distance in [0, 1, 2, 99, MAX_SCORE - FORCED_SCORE] {
+            let score = MAX_SCORE - distance;
+            assert_eq!(expand_score(compress_score(score)), score);
+            assert_eq!(expand_score(compress_score(-score)), -score);
+        }
+    }
+
+    #[test]
+    fn evaluations_are_clamped_below_forced_scores() {
+        for eval in [EVAL_LIMIT + 1, 50_000, FORCED_SCORE - 1] {
+            assert_eq!(expand_score(compress_score(eval)), EVAL_LIMIT);
+            assert_eq!(expand_score(compress_score(-eval)), -EVAL_LIMIT);
+        }
+    }
+
+    #[test]
+    fn other_check_bits_are_collisions() {
+        let tt = SharedTT::new(1);
+        let key = 0x1234_5678_9ABC_DEF0;
+        let other = key ^ 1 << 60; // Same bucket, other check bits
+        tt.store(key, entry(Some((2, 2, 3, 2)), 40, 5, TTFlag::Exact));
+        let mut collisions = 0;
+        assert!(tt.probe(other, &mut collisions).is_none());
+        assert_eq!(collisions, 1);
+        let found = tt.probe(key, &mut collisions).unwrap();
+        assert_same(&found, &entry(Some((2, 2, 3, 2)), 40, 5, TTFlag::Exact));
+        assert_eq!(collisions, 1);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let tt = SharedTT::new(1);
+        let entries = [
+            (0x0123_4567_89AB_CDEF, entry(Some((0, 4, 1, 4)), -35, 6, TTFlag::LowerBound)),
+            (0xFEDC_BA98_7654_3210, entry(None, MAX_SCORE - 3, 9, TTFlag::Exact)),
+            (0x0F0F_0F0F_0F0F_0F0F, entry(Some((7, 1, 6, 1)), 12, 1, TTFlag::UpperBound)),
+        ];
+        for (key, entry) in &entries {
+            tt.store(*key, *entry);
+        }
+        let path = std::env::temp_dir().join(format!("fianco_tt_{}.bin", std::process::id()));
+        assert_eq!(tt.save(&path, 2).unwrap(), 2);
+        let loaded = SharedTT::new(1);
+        let read = loaded.load(&path);
+        let bigger = SharedTT::new(2).load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), 2);
+        assert_eq!(bigger.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        let mut collisions = 0;
+        for (key, entry) in &entries[..2] {
+            assert_same(&loaded.probe(*key, &mut collisions).unwrap(), entry);
+        }
+        assert!(loaded.probe(entries[2].0, &mut collisions).is_none());
+    }
+}
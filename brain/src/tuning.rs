@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::Path;
+
+use crate::eval::EvalParams;
+use crate::{apply_move, evaluate_board, evaluate_stones, get_valid_moves, is_game_over, race, Board, Move, START_BOARD};
+
+const DEFAULT_SKIP_PLIES: usize = 8; // Opening positions are left to the book
+const DEFAULT_MAX_ROUNDS: usize = 1000;
+const QUIESCENCE_PLIES: usize = 12; // Capture sequences are played out this far at most
+// The weights that shape the evaluation of quiet, undecided positions;
+// `race_win` only scores races, which are left out of the fit
+const TUNED_PARAMS: [&str; 3] = ["stone", "advance", "edge"];
+
+const USAGE: &str = "usage: tune GAMES.jsonl... [--out FILE] [--start FILE] [--skip-plies N] [--max-rounds N]
+
+GAMES are JSON lines files of games from the start position, White first,
+as written by `arena.py --record`: {\"moves\": [[5, 3, 4, 3], ...], \"winner\": -1}
+(-1 White, 1 Black, 0 draw). The tuned weights are written to --out
+(tuned_eval.txt by default) for FiancoAI.load_eval_params; --start sets the
+weights to start from.";
+
+// A quiet position and the result of its game for White: 1 for a win,
+// 0.5 for a draw and 0 for a loss
+struct Sample {
+    board: Board,
+    result: f64,
+}
+
+/// Texel tuning of the evaluation weights from game records, run by the
+/// `tune` binary with the command line arguments (without the program name):
+///
+/// ```text
+/// cargo run --release --no-default-features --features tuner --bin tune -- games.jsonl --out weights.txt
+/// ```
+///
+/// Every position of the games past the opening is labelled with the result
+/// of its game and resolved to a quiet position by playing out the forced
+/// captures. The weights are then fitted so that the evaluation, mapped to a
+/// win probability by a logistic curve, predicts the results with the least
+/// mean squared error.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut games = Vec::new();
+    let mut out = "tuned_eval.txt".to_string();
+    let mut params = EvalParams::default();
+    let mut skip_plies = DEFAULT_SKIP_PLIES;
+    let mut max_rounds = DEFAULT_MAX_ROUNDS;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value\n\n{}", arg, USAGE));
+        let number = |value: &String| value.parse::<usize>().map_err(|_| format!("{}: '{}' is not a number", arg, value));
+        match arg.as_str() {
+            "--out" => out = value()?.clone(),
+            "--start" => {
+                let path = value()?;
+                params = EvalParams::load(Path::new(path)).map_err(|e| format!("Could not load {}: {}", path, e))?;
+            },
+            "--skip-plies" => skip_plies = number(value()?)?,
+            "--max-rounds" => max_rounds = number(value()?)?,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ => games.push(arg.clone()),
+        }
+    }
+    if games.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut samples = Vec::new();
+    for path in &games {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let (moves, winner) = parse_record(line).map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+            add_game(&mut samples, &moves, winner, skip_plies, &params).map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+        }
+    }
+    if samples.is_empty() {
+        return Err("no quiet, undecided positions in the games".to_string());
+    }
+    println!("{} positions from {} file(s)", samples.len(), games.len());
+
+    let scale = fit_scale(&samples, &params);
+    let start_error = mean_error(&samples, &params, scale);
+    println!("Scale {:.4}, error {:.6}", scale, start_error);
+    let error = local_search(&samples, &mut params, scale, max_rounds);
+    println!("Error {:.6} -> {:.6}", start_error, error);
+    for (name, value) in params.values() {
+        println!("  {} = {}", name, value);
+    }
+    params.save(Path::new(&out)).map_err(|e| format!("Could not write {}: {}", out, e))?;
+    println!("Written to {}", out);
+    Ok(())
+}
+
+// The moves and winner of one game record, e.g.
+// {"moves": [[5, 3, 4, 3], [3, 5, 4, 5]], "winner": -1}
+fn parse_record(line: &str) -> Result<(Vec<Move>, i8), String> {
+    let after = |key: &str| line.find(key).map(|start| &line[start + key.len()..]).ok_or(format!("no {} field", key));
+
+    let winner = after("\"winner\"")?.trim_start().trim_start_matches(':').trim_start();
+    let end = winner.find(|c: char| c != '-' && !c.is_ascii_digit()).unwrap_or(winner.len());
+    let winner = match winner[..end].parse::<i8>() {
+        Ok(winner) if (-1..=1).contains(&winner) => winner,
+        _ => return Err("the winner must be -1, 0 or 1".to_string()),
+    };
+
+    let moves = after("\"moves\"")?;
+    let start = moves.find('[').ok_or("no list of moves")?;
+    let mut depth = 0;
+    let mut end = None;
+    for (i, c) in moves[start..].char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            end = Some(start + i);
+            break;
+        }
+    }
+    let moves = &moves[start..end.ok_or("unterminated list of moves")?];
+    let numbers = moves
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse::<usize>().map_err(|_| format!("bad square {}", number)))
+        .collect::<Result<Vec<usize>, String>>()?;
+    if numbers.len() % 4 != 0 {
+        return Err("every move needs from and to row and column".to_string());
+    }
+    Ok((numbers.chunks(4).map(|m| (m[0], m[1], m[2], m[3])).collect(), winner))
+}
+
+// Replays a game from the start position and adds its positions from ply
+// `skip_plies` on, each resolved with `params`. Positions that are lost,
+// won or decided races say nothing about the weights and are left out.
+fn add_game(samples: &mut Vec<Sample>, moves: &[Move], winner: i8, skip_plies: usize, params: &EvalParams) -> Result<(), String> {
+    let result = match winner {
+        -1 => 1.0,
+        1 => 0.0,
+        _ => 0.5,
+    };
+    let mut board = START_BOARD;
+    let mut player = -1;
+    for (ply, &mv) in moves.iter().enumerate() {
+        if is_game_over(&board, player) || !get_valid_moves(&board, player).contains(&mv) {
+            return Err(format!("move {:?} is not legal after {} moves", mv, ply));
+        }
+        if ply >= skip_plies {
+            let (_, quiet, to_move) = resolve(&board, player, QUIESCENCE_PLIES, params);
+            if !is_game_over(&quiet, to_move) && race::solve(&quiet, to_move).is_none() {
+                samples.push(Sample { board: quiet, result });
+            }
+        }
+        apply_move(&mut board, player, mv);
+        player = -player;
+    }
+    Ok(())
+}
+
+// Plays out the forced captures from the position, both sides choosing the
+// best sequence by `evaluate_board`, and returns its score for `player`
+// with the quiet position it ends in and the side to move there
+fn resolve(board: &Board, player: i8, plies: usize, params: &EvalParams) -> (i32, Board, i8) {
+    let moves = get_valid_moves(board, player);
+    let is_capture = |&(from_row, _, to_row, _): &Move| from_row.abs_diff(to_row) == 2;
+    if plies == 0 || is_game_over(board, player) || !moves.iter().any(is_capture) {
+        return (-player as i32 * evaluate_board(board, player, params), *board, player);
+    }
+    let mut best: Option<(i32, Board, i8)> = None;
+    for mv in moves {
+        let mut after = *board;
+        apply_move(&mut after, player, mv);
+        let (score, quiet, to_move) = resolve(&after, -player, plies - 1, params);
+        let better = match best {
+            Some((best_score, _, _)) => -score > best_score,
+            None => true,
+        };
+        if better {
+            best = Some((-score, quiet, to_move));
+        }
+    }
+    best.unwrap()
+}
+
+// Win probability for White of an evaluation, on a logistic curve that
+// `scale` stretches
+fn win_probability(eval: i32, scale: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scale * eval as f64 / 400.0))
+}
+
+// The samples are quiet and undecided, so `evaluate_board` comes down to
+// `evaluate_stones` for them
+fn mean_error(samples: &[Sample], params: &EvalParams, scale: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|sample| (sample.result - win_probability(evaluate_stones(&sample.board, params), scale)).powi(2))
+        .sum();
+    total / samples.len() as f64
+}
+
+// The scale that best fits the starting weights, by ever finer scans; the
+// weights are then tuned with it fixed
+fn fit_scale(samples: &[Sample], params: &EvalParams) -> f64 {
+    let (mut best, mut step) = (1.0, 0.5);
+    for _ in 0..4 {
+        let candidates = (-10..=10).map(|i| best + i as f64 * step).filter(|&scale| scale > 0.0);
+        best = candidates
+            .map(|scale| (scale, mean_error(samples, params, scale)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(best, |(scale, _)| scale);
+        step /= 10.0;
+    }
+    best
+}
+
+// Texel's local search: changes one weight by one unit at a time and keeps
+// every change that lowers the error, until a whole round changes nothing
+fn local_search(samples: &[Sample], params: &mut EvalParams, scale: f64, max_rounds: usize) -> f64 {
+    let mut best = mean_error(samples, params, scale);
+    for round in 1..=max_rounds {
+        let mut improved = false;
+        for name in TUNED_PARAMS {
+            for delta in [1, -1] {
+                let mut candidate = *params;
+                candidate.set(name, params.get(name).unwrap() + delta).unwrap();
+                let error = mean_error(samples, &candidate, scale);
+                if error < best {
+                    *params = candidate;
+                    best = error;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        let values: Vec<String> = TUNED_PARAMS.iter().map(|&name| format!("{}={}", name, params.get(name).unwrap())).collect();
+        println!("Round {}: error {:.6}, {}", round, best, values.join(" "));
+        if !improved {
+            break;
+        }
+    }
+    best
+}
@@ -2,14 +2,23 @@ import numpy as np
 from fianco_brain import FiancoAI  # Import the Rust AI function
 
 class AIController:
-    def __init__(self, player, game, depth=20, time=60): # Depth search will stop after 60 seconds, and time search will stop after depth 20 is reached.
+    def __init__(self, player, game, depth=20, time=60, threads=1, algorithm='alphabeta', lines=1, ponder=False, skill=20, book=None): # Depth search will stop after 60 seconds, and time search will stop after depth 20 is reached.
         self.player = player  # -1 for White, 1 for Black
         self.game = game
         self.depth = depth
         self.time = time
-        self.ai = FiancoAI(player)
-
-    def get_move(self, board_state):
+        self.lines = lines  # > 1 runs a multi-PV search that also reports the best alternatives
+        self.last_lines = []  # (score, pv, depth) of the last search, best first
+        self.last_stats = None  # SearchStats of the last search: nodes, nps, seldepth, TT hits, cutoffs, time, hashfull
+        self.ponder = ponder  # Keep searching the expected reply while the opponent thinks
+        self.ai = FiancoAI(player, threads, algorithm)  # threads > 1 enables the Lazy SMP parallel search; algorithm is 'alphabeta' or 'mcts'
+        self.ai.set_skill_level(skill)  # 0-20; below 20 the engine plays weaker on purpose
+        if book is not None:
+            self.ai.load_book(book)  # Opening book file written by book.py
+        self.stop_handle = self.ai.get_stop_handle()  # Usable from other threads while the search runs
+        self.info_handle = self.ai.get_info_handle()  # Progress reports of the running search, likewise
+
+    def get_move(self, board_state, history=None):
         # Ensure the board_state is a NumPy array of type int8
         if not isinstance(board_state, np.ndarray):
             board_state = np.array(board_state, dtype=np.int8)
@@ -19,15 +28,50 @@ class AIController:
         player = self.player
         depth = self.depth  # Adjust search depth as needed
 
+        if history is not None:
+            # Earlier positions of the game, oldest first, for repetition detection
+            self.ai.set_history([np.asarray(position, dtype=np.int8) for position in history])
+
+        book_move = self.ai.get_book_move(board_state, player)
+        if book_move is not None:
+            # Opening book hit: no search, so no lines or statistics
+            self.last_lines = [(0, [book_move], 0)]
+            self.last_stats = None
+            return book_move
+
         try:
-            pv = self.ai.get_best_move(board_state, player, depth, self.time)
+            if self.lines > 1:
+                self.last_lines = self.ai.analyse(board_state, player, depth, self.time, self.lines)
+                pv = self.last_lines[0]
+            else:
+                pv = self.ai.get_best_move(board_state, player, depth, self.time)
+                self.last_lines = [(pv[0], pv[1], depth)]
+            self.last_stats = self.ai.get_stats()
             best_score = pv[0]
             from_row, from_col, to_row, to_col = pv[1][0] 
             print(f"Current eval: {best_score}")
+            if self.ponder and len(pv[1]) >= 2:
+                self.ai.start_ponder(board_state, player, pv[1][0], pv[1][1], depth, self.lines)
             return from_row, from_col, to_row, to_col
         except ValueError:
             self.game.export_position()
             raise NotImplementedError("AI has no valid moves.")
+
+    def stop(self):
+        # Ends a running get_move early; it returns the best move found so far
+        self.stop_handle.stop()
+
+    def poll_info(self):
+        # SearchInfo reports (depth, multipv, score, pv, nodes, time) sent since the last call
+        return self.info_handle.poll()
+
+    def stop_ponder(self):
+        self.ai.stop_ponder()
+
+    def new_game(self):
+        # Forget the previous game's positions and search results and cancel pondering
+        self.ai.new_game()
+        self.ai.clear_hash()
         
 
 
@@ -2,14 +2,19 @@ import pygame
 import numpy as np
 import sys
 from controller import AIController  # Import the Controller class
+from fianco_brain import skill_elo
 import re
 import ast
+import threading
+import os
 
 # Constants
 ROWS, COLS = 9, 9
 SQUARE_SIZE = 60  # Size of each square in pixels
 MARGIN = 50       # Margin size for labels
 MOVE_PANEL_WIDTH = 200  # Width of the move history panel
+ANALYSIS_LINES = 3  # Lines shown below the move history when an AI player has analysis switched on
+BOOK_FILE = "fianco_book.bin"  # Opening book used by the AI players when present (see book.py)
 BUTTON_WIDTH = 80
 BUTTON_HEIGHT = 30
 WIDTH = SQUARE_SIZE * COLS + MARGIN * 2 + MOVE_PANEL_WIDTH
@@ -63,16 +68,26 @@ class FiancoGame:
         self.player_types = { -1: 'human', 1: 'human' }
         # ai mode per side: 'depth' or 'time'
         self.ai_mode = { -1: 'depth', 1: 'depth' }
+        # search algorithm per side: 'alphabeta' or 'mcts'
+        self.ai_algorithm = { -1: 'alphabeta', 1: 'alphabeta' }
         # selected depth per side (used now)
         self.ai_depth = { -1: 4, 1: 4 }
         # selected time per side in seconds (stored for later integration)
         self.ai_time = { -1: 5, 1: 5 }
+        # skill level per side: 0 (weakest) to 20 (full strength)
+        self.ai_skill = { -1: 20, 1: 20 }
+        # show the best alternatives per side: a multi-PV search that costs about ANALYSIS_LINES normal ones
+        self.ai_analysis = { -1: False, 1: False }
 
         # Controllers for AI players
         self.controllers = {
             -1: None,
             1: None
         }
+        # AI searches run in a worker thread so the window stays responsive
+        self.ai_thread = None
+        self.ai_result = None
+        self.live_lines = {}  # multipv -> (score, pv, depth) reported by the running search
 
         # Buttons
         button_x = MARGIN * 2 + COLS * SQUARE_SIZE + (MOVE_PANEL_WIDTH - BUTTON_WIDTH * 2 - 10) // 2
@@ -121,6 +136,7 @@ class FiancoGame:
         self.board_state = self.initial_board_state.copy()
         self.white_moves = []
         self.black_moves = []
+        self.analysis = None  # (player, lines) of the last AI search
 
     def draw_board(self):
         # Fill background with margin color
@@ -221,8 +237,10 @@ class FiancoGame:
         col2_x = col1_x + MOVE_PANEL_WIDTH // 2 - 10
         start_y = MARGIN + (BUTTON_HEIGHT + 10) * 4 + 20
         line_height = 20
+        # Leave room at the bottom for the engine lines
+        analysis_height = (ANALYSIS_LINES + 2) * line_height if self.analysis else 0
         # Calculate how many moves can be displayed
-        max_displayed_moves = (HEIGHT - start_y - line_height) // line_height
+        max_displayed_moves = (HEIGHT - start_y - line_height - analysis_height) // line_height
         max_moves = max(len(self.white_moves), len(self.black_moves))
         # Determine the range of moves to display
         start_index = max(0, max_moves - max_displayed_moves)
@@ -239,6 +257,19 @@ class FiancoGame:
             if idx < len(self.black_moves):
                 move_text = self.font.render(self.black_moves[idx], True, TEXT_COLOR)
                 self.screen.blit(move_text, (col2_x, start_y + i * line_height))
+        if self.analysis:
+            self.draw_analysis(col1_x, HEIGHT - analysis_height, line_height)
+
+    def draw_analysis(self, x, y, line_height):
+        # Best moves of the last AI search, each with its score for the side that moved
+        player, lines = self.analysis
+        header = self.font.render(f"{self.get_player_label(player)} engine lines", True, TEXT_COLOR)
+        self.screen.blit(header, (x, y))
+        for i, (score, pv, depth) in enumerate(lines[:ANALYSIS_LINES]):
+            from_row, from_col, to_row, to_col = pv[0]
+            move = self.coord_to_notation(from_row, from_col) + '->' + self.coord_to_notation(to_row, to_col)
+            text = self.font.render(f"{i + 1}. {move}  {-player * score:+d} (d{depth})", True, TEXT_COLOR)
+            self.screen.blit(text, (x, y + (i + 1) * line_height))
 
     def draw_buttons(self):
         mouse_pos = pygame.mouse.get_pos()
@@ -253,7 +284,10 @@ class FiancoGame:
         # Draw setup/new game button
         self.draw_button(self.setup_menu_button_rect, 'New Game', mouse_pos)
         # Draw play/pause button
-        button_text = 'Play' if self.paused else 'Pause'
+        if self.ai_thread is not None:
+            button_text = 'Move now'
+        else:
+            button_text = 'Play' if self.paused else 'Pause'
         self.draw_button(self.play_button_rect, button_text, mouse_pos)
 
     def draw_button(self, rect, text, mouse_pos):
@@ -372,7 +406,9 @@ class FiancoGame:
                 self.black_moves.copy()
             ))
             # Restore the previous state
+            self.cancel_ai_search()
             self.board_state, self.current_player, self.white_moves, self.black_moves = self.undo_stack.pop()
+            self.stop_pondering()
             self.selected_piece = None
             self.valid_moves = np.array([], dtype=np.int8).reshape(0, 4)
             self.game_over = False
@@ -389,7 +425,9 @@ class FiancoGame:
                 self.black_moves.copy()
             ))
             # Restore the next state
+            self.cancel_ai_search()
             self.board_state, self.current_player, self.white_moves, self.black_moves = self.redo_stack.pop()
+            self.stop_pondering()
             self.selected_piece = None
             self.valid_moves = np.array([], dtype=np.int8).reshape(0, 4)
             self.game_over = False
@@ -397,6 +435,18 @@ class FiancoGame:
             self.draw_board()
 
 
+    def stop_pondering(self):
+        # The position changed outside the game flow; background searches are stale
+        for controller in self.controllers.values():
+            if isinstance(controller, AIController):
+                controller.stop_ponder()
+
+    def start_new_game(self):
+        # Positions of the previous game no longer count for repetitions
+        for controller in self.controllers.values():
+            if isinstance(controller, AIController):
+                controller.new_game()
+
     def check_for_win(self):
         player = self.current_player
         target_row = 8 if player == 1 else 0
@@ -427,6 +477,10 @@ class FiancoGame:
         x, y = pos
         # Check if click is on play/pause button
         if self.play_button_rect.collidepoint(x, y):
+            if self.ai_thread is not None:
+                # Make the AI play its best move so far
+                self.controllers[self.current_player].stop()
+                return
             self.paused = not self.paused  # Toggle paused state
             self.draw_board()  # Redraw to update button label
             return
@@ -477,6 +531,7 @@ class FiancoGame:
             self.valid_moves = np.array([], dtype=np.int8).reshape(0, 4)
 
     def reset_game(self):
+        self.cancel_ai_search()
         # Reset the board state
         self.board_state = self.initial_board_state.copy()
         self.current_player = -1
@@ -486,6 +541,8 @@ class FiancoGame:
         self.black_moves = []
         self.undo_stack = []
         self.redo_stack = []
+        self.analysis = None
+        self.start_new_game()
         self.game_over = False
         self.paused = False
         self.draw_board()
@@ -508,16 +565,53 @@ class FiancoGame:
             f.write("']")
         print('Position exported to fianco_export.txt')
 
+    def run_ai_search(self, controller, board, history):
+        # Worker thread: the search releases the GIL, so the game loop keeps running
+        try:
+            self.ai_result = controller.get_move(board, history)
+        except NotImplementedError as e:
+            self.ai_result = e
+
+    def cancel_ai_search(self):
+        # Stop a running search and throw its result away
+        if self.ai_thread is not None:
+            self.controllers[self.current_player].stop()
+            self.ai_thread.join()
+            self.ai_thread = None
+            self.ai_result = None
+
+    def show_search_info(self, controller):
+        # Live analysis while the search runs: the latest line per multi-PV slot
+        infos = controller.poll_info()
+        if not infos:
+            return
+        for info in infos:
+            self.live_lines[info.multipv] = (info.score, info.pv, info.depth)
+        self.analysis = (self.current_player, [self.live_lines[k] for k in sorted(self.live_lines)])
+        self.draw_board()
+
     def handle_ai_move(self):
         controller = self.controllers[self.current_player]
         if controller is None:
             return
-        try:
-            move = controller.get_move(self.board_state)
-        except NotImplementedError as e:
-            print(e)
+        if self.ai_thread is None:
+            self.ai_result = None
+            self.live_lines = {}
+            history = [state[0].copy() for state in self.undo_stack]  # Positions before the current one
+            self.ai_thread = threading.Thread(target=self.run_ai_search, args=(controller, self.board_state.copy(), history), daemon=True)
+            self.ai_thread.start()
+            self.draw_board()  # Show the 'Move now' button
+            return
+        if self.ai_thread.is_alive():
+            self.show_search_info(controller)
+            return
+        self.ai_thread = None
+        move = self.ai_result
+        if isinstance(move, NotImplementedError):
+            print(move)
             self.game_over = True
             return
+        self.analysis = (self.current_player, controller.last_lines)
         from_row, from_col, to_row, to_col = move
         # pygame.time.wait(500)  # Delay for better visualization
         self.selected_piece = None
@@ -572,6 +666,46 @@ class FiancoGame:
             yy += 42
 
             if self.player_types[who] == 'ai':
+                # Engine
+                tip = self.font.render("Engine:", True, (0,0,0))
+                self.screen.blit(tip, (x, yy))
+                alphabeta_rect = pygame.Rect(x+110, yy-6, 90, 28)
+                mcts_rect      = pygame.Rect(x+210, yy-6, 90, 28)
+                btn(alphabeta_rect, "AlphaBeta", self.ai_algorithm[who] == 'alphabeta')
+                btn(mcts_rect,      "MCTS",      self.ai_algorithm[who] == 'mcts')
+                click_targets.append(("algorithm", who, "alphabeta", alphabeta_rect))
+                click_targets.append(("algorithm", who, "mcts",      mcts_rect))
+                yy += 42
+
+                # Skill +/- selector
+                tip = self.font.render("Skill:", True, (0,0,0))
+                self.screen.blit(tip, (x, yy))
+                minus_rect = pygame.Rect(x+110, yy-6, 28, 28)
+                val_rect   = pygame.Rect(x+110+32, yy-6, 60, 28)
+                plus_rect  = pygame.Rect(x+110+32+60, yy-6, 28, 28)
+                btn(minus_rect, "−")
+                btn(plus_rect,  "+")
+                pygame.draw.rect(self.screen, (245,245,245), val_rect, border_radius=8)
+                val_text = self.font.render(str(self.ai_skill[who]), True, (0,0,0))
+                self.screen.blit(val_text, val_text.get_rect(center=val_rect.center))
+                click_targets.append(("skill", who, "minus", minus_rect))
+                click_targets.append(("skill", who, "plus",  plus_rect))
+                if self.ai_skill[who] < 20:
+                    elo = self.font.render(f"~{skill_elo(self.ai_skill[who])} Elo", True, (0,0,0))
+                    self.screen.blit(elo, (x+110+32+60+40, yy))
+                yy += 42
+
+                # Analysis lines: off plays from a normal single-line search
+                tip = self.font.render("Lines:", True, (0,0,0))
+                self.screen.blit(tip, (x, yy))
+                lines_off_rect = pygame.Rect(x+110, yy-6, 90, 28)
+                lines_on_rect  = pygame.Rect(x+210, yy-6, 90, 28)
+                btn(lines_off_rect, "1", not self.ai_analysis[who])
+                btn(lines_on_rect,  str(ANALYSIS_LINES), self.ai_analysis[who])
+                click_targets.append(("analysis", who, False, lines_off_rect))
+                click_targets.append(("analysis", who, True,  lines_on_rect))
+                yy += 42
+
                 # Mode
                 tip = self.font.render("AI Mode:", True, (0,0,0))
                 self.screen.blit(tip, (x, yy))
@@ -632,7 +766,7 @@ class FiancoGame:
             top_y   = MARGIN + 150
 
             panel_w = WIDTH//2 - 40
-            panel_h = 250
+            panel_h = 372
             pygame.draw.rect(self.screen, (255,255,255), pygame.Rect(left_x-10, top_y-20, panel_w, panel_h), border_radius=12)
             pygame.draw.rect(self.screen, (255,255,255), pygame.Rect(right_x-10, top_y-20, panel_w, panel_h), border_radius=12)
 
@@ -659,11 +793,20 @@ class FiancoGame:
                                 self.player_types[who] = val  # 'human' or 'ai'
                             elif kind == "mode":
                                 self.ai_mode[who] = val       # 'depth' or 'time'
+                            elif kind == "algorithm":
+                                self.ai_algorithm[who] = val  # 'alphabeta' or 'mcts'
+                            elif kind == "analysis":
+                                self.ai_analysis[who] = val   # True shows ANALYSIS_LINES lines
                             elif kind == "depth":
                                 if val == "minus":
                                     self.ai_depth[who] = max(1, self.ai_depth[who] - 1)
                                 else:
                                     self.ai_depth[who] = min(20, self.ai_depth[who] + 1)
+                            elif kind == "skill":
+                                if val == "minus":
+                                    self.ai_skill[who] = max(0, self.ai_skill[who] - 1)
+                                else:
+                                    self.ai_skill[who] = min(20, self.ai_skill[who] + 1)
                             elif kind == "time":
                                 if val == "minus":
                                     self.ai_time[who] = max(1, self.ai_time[who] - 1)
@@ -679,20 +822,28 @@ class FiancoGame:
         self.controllers = { -1: None, 1: None }
         for player in (-1, 1):
             if self.player_types[player] == 'ai':
+                algorithm = self.ai_algorithm[player]
+                skill = self.ai_skill[player]
+                lines = ANALYSIS_LINES if self.ai_analysis[player] else 1
+                # Ponder on the human's time; two engines would only compete for the CPU.
+                # A weakened engine searches too shallow for pondering to matter
+                ponder = self.player_types[-player] == 'human' and skill == 20
+                book = BOOK_FILE if os.path.exists(BOOK_FILE) else None
                 if self.ai_mode[player] == 'depth':
                     depth = int(self.ai_depth[player])
-                    self.controllers[player] = AIController(player, self, depth=depth)
-                    print(f"Player {player} is AI (depth {depth})")
+                    self.controllers[player] = AIController(player, self, depth=depth, algorithm=algorithm, lines=lines, ponder=ponder, skill=skill, book=book)
+                    print(f"Player {player} is AI ({algorithm}, depth {depth}, skill {skill})")
                 else:
                     # time mode selected — not wired into AI yet, just store variable
                     tsec = int(self.ai_time[player])
                     # Choose a default depth for now so code keeps running
-                    self.controllers[player] = AIController(player, self, time=tsec)
-                    print(f"Player {player} is AI (time {tsec}s)")
+                    self.controllers[player] = AIController(player, self, time=tsec, algorithm=algorithm, lines=lines, ponder=ponder, skill=skill, book=book)
+                    print(f"Player {player} is AI ({algorithm}, time {tsec}s, skill {skill})")
 
     def open_setup_menu(self):
         """Pause the game, open setup menu, apply choices, and restart the match."""
         # Pause and clear UI selections
+        self.cancel_ai_search()
         self.paused = True
         self.selected_piece = None
         self.valid_moves = np.array([], dtype=np.int8).reshape(0, 4)